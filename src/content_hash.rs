@@ -0,0 +1,107 @@
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::Path;
+
+/// Calculates the SHA-256 hash of a file on disk, as a lowercase hex string.
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 8192];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(to_hex(&hasher.finalize()))
+}
+
+/// Calculates the SHA-256 hash of some bytes, as a lowercase hex string.
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    to_hex(&Sha256::digest(bytes))
+}
+
+/// Hashes everything that is read through it, see `hash_file`.
+pub struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+    bytes_read: u64,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub fn new(inner: R) -> HashingReader<R> {
+        HashingReader {
+            inner,
+            hasher: Sha256::new(),
+            bytes_read: 0,
+        }
+    }
+
+    /// The hash and size of everything that was read so far.
+    pub fn finish(self) -> (String, u64) {
+        (to_hex(&self.hasher.finalize()), self.bytes_read)
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.bytes_read += read as u64;
+        Ok(read)
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEST_FILES_PATH: &str = "tests/files";
+
+    #[test]
+    fn known_hash() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().join("hello.txt");
+        std::fs::write(&path, "hello").unwrap();
+
+        assert_eq!(
+            hash_file(&path).unwrap(),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+
+    #[test]
+    fn bytes_and_files_hash_the_same() {
+        let path = Path::new(TEST_FILES_PATH).join("swords/tall.png");
+        let bytes = std::fs::read(&path).unwrap();
+
+        assert_eq!(hash_bytes(&bytes), hash_file(&path).unwrap());
+    }
+
+    #[test]
+    fn hashing_reader_hashes_what_is_read() {
+        let mut reader = HashingReader::new(&b"hello"[..]);
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+
+        assert_eq!(read, b"hello");
+        assert_eq!(reader.finish(), (hash_bytes(b"hello"), 5));
+    }
+
+    #[test]
+    fn different_files_have_different_hashes() {
+        let test_files = Path::new(TEST_FILES_PATH);
+
+        assert_ne!(
+            hash_file(&test_files.join("swords/tall.png")).unwrap(),
+            hash_file(&test_files.join("swords/wide.png")).unwrap()
+        );
+    }
+}
@@ -1,131 +1,4164 @@
-use crate::stores::file_store::{File, FileId, FileStore, KnownExtension};
-use crate::stores::traits::IndexedStore;
-use anyhow::{Context, Result};
-use std::collections::hash_map::Iter;
+use crate::content_hash;
+use crate::error::DataError;
+use crate::file_backend::{DiskBackend, FileBackend};
+#[cfg(feature = "image")]
+use crate::images;
+use crate::stores::asset_store::{Asset, AssetId, AssetStore};
+use crate::stores::collection_store::{Collection, CollectionId, CollectionStore};
+use crate::stores::file_store::{
+    validate_title, File, FileId, FileStore, KnownExtension, SystemTag,
+};
+use crate::stores::traits::{describe_count, IndexedStore, StoreIter};
+use std::collections::HashMap;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Name of the file in the save directory that holds the index of all the stores.
+#[cfg(feature = "serde")]
+const INDEX_FILE_NAME: &str = "index.json";
+
+/// Version of the index that `Data::save` writes, saved along with it.
+/// Indexes from before there was a version are version 0, which is read the same as version 1.
+#[cfg(feature = "serde")]
+const INDEX_VERSION: u32 = 1;
+
+/// The index is written here first, and only moved over the real index once it is complete.
+#[cfg(feature = "serde")]
+const INDEX_TEMP_FILE_NAME: &str = "index.json.tmp";
+
+/// Name of the directory inside an exported zip that holds the stored files.
+#[cfg(feature = "zip")]
+const ZIP_FILES_DIR_NAME: &str = "files";
+
+/// Name of the directory in the save directory where thumbnails are cached.
+#[cfg(feature = "image")]
+const THUMBNAILS_DIR_NAME: &str = "thumbnails";
+
+/// Quality of the jpeg files that `Data::convert_file` writes, from 1 to 100.
+#[cfg(feature = "image")]
+const CONVERTED_JPEG_QUALITY: u8 = 90;
+
+/// Something that changed in one of the stores, see `Data::set_on_change`.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum StoreEvent {
+    FileAdded(FileId),
+    FileRemoved(FileId),
+    AssetAdded(AssetId),
+    AssetRemoved(AssetId),
+}
+
+/// Something that is wrong with the stored data, see `Data::check_integrity`.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum IntegrityIssue {
+    /// The asset refers to a file that is not in the file store.
+    DanglingFile { asset: AssetId, file: FileId },
+    /// The file is in the file store, but not in the files directory.
+    MissingOnDisk(FileId),
+}
+
+/// The stores that `Data` keeps, to do the same thing to each of them without repeating it.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+enum StoreKind {
+    Files,
+    Assets,
+    Collections,
+}
+
+impl StoreKind {
+    /// Assets refer to files and collections refer to assets, so the stores that are referred
+    /// to come first.
+    const ALL: [StoreKind; 3] = [StoreKind::Files, StoreKind::Assets, StoreKind::Collections];
+}
+
+/// Result of importing multiple files at once.
+/// A failing file does not stop the import, it ends up in `failed`.
+pub struct ImportSummary {
+    pub imported: Vec<FileId>,
+    pub failed: Vec<(PathBuf, DataError)>,
+    /// Imported images whose properties, like the dimensions, could not be read.
+    pub undecoded: Vec<(FileId, DataError)>,
+}
+
+impl ImportSummary {
+    fn record(&mut self, path: PathBuf, result: Result<(FileId, Option<DataError>), DataError>) {
+        match result {
+            Ok((id, undecoded)) => {
+                self.imported.push(id);
+                if let Some(e) = undecoded {
+                    self.undecoded.push((id, e));
+                }
+            }
+            Err(e) => self.failed.push((path, e)),
+        }
+    }
+}
+
+/// Result of removing multiple files at once, see `Data::remove_files`.
+/// A file that can't be removed does not stop the others, it ends up in `failed`.
+pub struct RemoveReport {
+    pub removed: Vec<FileId>,
+    pub failed: Vec<(FileId, DataError)>,
+}
+
+/// Overview of everything that is stored, see `Data::summary`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Summary {
+    pub file_count: usize,
+    pub asset_count: usize,
+    pub collection_count: usize,
+    /// Files that are raster images, see `KnownExtension::is_image`.
+    pub image_count: usize,
+    pub total_size_bytes: u64,
+    /// How many files there are of each extension, see `Data::count_by_extension`.
+    pub by_extension: HashMap<String, usize>,
+}
+
+/// How to order files, see `Data::list_files_sorted`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SortKey {
+    /// By title, ignoring case.
+    TitleAsc,
+    TitleDesc,
+    IdAsc,
+    /// Biggest files first.
+    SizeDesc,
+    /// Oldest files first.
+    CreatedAtAsc,
+}
+
+/// How to make a thumbnail, see `Data::generate_thumbnail`.
+#[cfg(feature = "image")]
+#[derive(Debug)]
+pub struct ThumbnailOptions {
+    /// Has to be a raster format, see `KnownExtension::is_image`.
+    pub format: KnownExtension,
+    /// How long the longest side of the thumbnail is, in pixels.
+    pub max_dim: u32,
+    /// From 1 to 100, only used for jpeg thumbnails.
+    pub jpeg_quality: u8,
+}
+
+#[cfg(feature = "image")]
+impl Default for ThumbnailOptions {
+    fn default() -> ThumbnailOptions {
+        ThumbnailOptions {
+            format: KnownExtension::Png,
+            max_dim: 128,
+            jpeg_quality: 80,
+        }
+    }
+}
+
+/// How the files directory differs from the file store, see `Data::reconcile_disk`.
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+    /// Files with the contents of a known file, but under the wrong name.
+    pub fixed: Vec<(PathBuf, FileId)>,
+    /// Known files that are not in the files directory.
+    pub missing: Vec<FileId>,
+    /// Files in the files directory that don't belong to any known file.
+    pub unexpected: Vec<PathBuf>,
+}
+
+/// What `Data::import_directory` would do, see `Data::preview_directory`.
+pub struct ImportPreview {
+    /// The files that would be copied into the files directory, in import order.
+    pub new: Vec<PathBuf>,
+    pub skipped: Vec<(PathBuf, SkipReason)>,
+}
+
+/// Why a file would not be copied, see `ImportPreview`.
+#[derive(Debug)]
+pub enum SkipReason {
+    UnknownExtension,
+    /// A file with the same contents is already stored.
+    AlreadyStored(FileId),
+    /// An earlier file in the same import has the same contents.
+    SameAs(PathBuf),
+    /// Importing would fail, for example because the file can't be read.
+    Failed(DataError),
+}
+
+/// What happens to a single file in `Data::import_directory_parallel`.
+#[cfg(feature = "rayon")]
+enum ImportStep {
+    Done(Result<FileId, DataError>),
+    /// A new file, still to be copied to the destination.
+    Copy(FileId, PathBuf),
+    /// Has the same contents as the file at this index, which is new as well.
+    SameAs(usize),
+}
+
+/// What `Data::save` writes: the stores, with the version of the index next to them.
+#[cfg(feature = "serde")]
+#[derive(Serialize)]
+struct SavedIndex<'a> {
+    version: u32,
+    #[serde(flatten)]
+    data: &'a Data,
+}
+
+/// Only the version of the index, to check it before reading the rest.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct IndexVersion {
+    #[serde(default)]
+    version: u32,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Data {
+    // The directories are not part of the index, they are passed in on load.
+    #[cfg_attr(feature = "serde", serde(skip))]
     save_dir: PathBuf,
+    #[cfg_attr(feature = "serde", serde(skip))]
     files_dir: PathBuf,
+    #[cfg(feature = "image")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    thumbnails_dir: PathBuf,
     files: FileStore,
+    assets: AssetStore,
+    /// Missing from indexes from before there were collections.
+    #[cfg_attr(feature = "serde", serde(default))]
+    collections: CollectionStore,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    on_change: Option<Box<dyn FnMut(StoreEvent)>>,
+    /// Where the contents of the files are kept, see `DataBuilder::backend`.
+    #[cfg_attr(feature = "serde", serde(skip, default = "disk_backend"))]
+    backend: Box<dyn FileBackend>,
+    /// Whether to check that imported images really are images, see `DataBuilder::verify_images`.
+    #[cfg(feature = "image")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    verify_images: bool,
 }
 
 impl Data {
     /// - `save_dir`: The directory to save the data files.
     /// - `files_dir`: The directory where the actual files will be stored.
+    ///
     /// Will create both when they don't exist.
-    pub fn new(save_dir: &Path, files_dir: &Path) -> Result<Data> {
-        // Make sure both directories exist.
-        std::fs::create_dir_all(save_dir).with_context(|| {
-            format!(
-                "Could not create save directory at: \"{}\"",
-                save_dir.display()
-            )
-        })?;
-        std::fs::create_dir_all(files_dir).with_context(|| {
-            format!(
-                "Could not create files directory at: \"{}\"",
-                save_dir.display()
-            )
-        })?;
+    /// Use a `DataBuilder` for more control.
+    pub fn new(save_dir: &Path, files_dir: &Path) -> Result<Data, DataError> {
+        DataBuilder::new(save_dir, files_dir).build()
+    }
 
-        Ok(Data {
-            save_dir: PathBuf::from(save_dir),
-            files_dir: PathBuf::from(save_dir),
-            files: FileStore::new(),
-        })
+    /// Loads the index from `save_dir`, see `Data::save`.
+    /// When there is no index, the files are recovered from `files_dir`.
+    /// For a new `files_dir`, this behaves like `Data::new`.
+    /// Use `DataBuilder::load` for more control.
+    #[cfg(feature = "serde")]
+    pub fn load(save_dir: &Path, files_dir: &Path) -> Result<Data, DataError> {
+        DataBuilder::new(save_dir, files_dir).load()
+    }
+
+    /// Writes the index of all the stores to a file in the save directory.
+    /// The files themselves are already on disk, so they are not touched.
+    /// The index is replaced in one go, so when saving fails halfway the old index is kept.
+    #[cfg(feature = "serde")]
+    pub fn save(&self) -> Result<(), DataError> {
+        let index = serde_json::to_string(&self.saved_index())?;
+
+        std::fs::create_dir_all(&self.save_dir).map_err(DataError::io(&self.save_dir))?;
+        write_index(&self.save_dir, index.as_bytes())
+    }
+
+    #[cfg(feature = "serde")]
+    fn saved_index(&self) -> SavedIndex<'_> {
+        SavedIndex {
+            version: INDEX_VERSION,
+            data: self,
+        }
+    }
+
+    /// Bundles the index and every stored file into a single zip file at `dest`.
+    /// The files keep their id-based names, in a `files` directory in the zip.
+    #[cfg(feature = "zip")]
+    pub fn export_zip(&self, dest: &Path) -> Result<(), DataError> {
+        let zip_file = std::fs::File::create(dest).map_err(DataError::io(dest))?;
+        let mut zip = zip::ZipWriter::new(zip_file);
+        let options = zip::write::SimpleFileOptions::default();
+
+        zip.start_file(INDEX_FILE_NAME, options)?;
+        serde_json::to_writer(&mut zip, &self.saved_index())?;
+
+        let mut ids: Vec<FileId> = self.files.ids().copied().collect();
+        ids.sort();
+        for id in ids {
+            let path = self.file_path(id).ok_or(DataError::FileNotFound(id))?;
+            let file_name = self.files.get(id).unwrap().file_name();
+            zip.start_file(
+                format!("{}/{}", ZIP_FILES_DIR_NAME, file_name.display()),
+                options,
+            )?;
+            let bytes = self.backend.read(&path).map_err(DataError::io(&path))?;
+            std::io::Write::write_all(&mut zip, &bytes).map_err(DataError::io(&path))?;
+        }
+
+        zip.finish()?;
+        Ok(())
+    }
+
+    /// Unpacks a zip made by `Data::export_zip` into the given directories,
+    /// and loads the bundled index from there, like `Data::load`.
+    /// Files that are already in `files_dir` with the same name are overwritten.
+    /// Will return an error if `save_dir` already has an index, before unpacking anything.
+    #[cfg(feature = "zip")]
+    pub fn import_zip(src: &Path, save_dir: &Path, files_dir: &Path) -> Result<Data, DataError> {
+        let index_path = save_dir.join(INDEX_FILE_NAME);
+        if index_path.exists() {
+            return Err(DataError::IndexExists(index_path));
+        }
+
+        let zip_file = std::fs::File::open(src).map_err(DataError::io(src))?;
+        let mut zip = zip::ZipArchive::new(zip_file)?;
+
+        std::fs::create_dir_all(save_dir).map_err(DataError::io(save_dir))?;
+        std::fs::create_dir_all(files_dir).map_err(DataError::io(files_dir))?;
+
+        // The index is written last, so there only is one when all the files are there.
+        let mut index = None;
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i)?;
+            // Only the index and the files directly in the files directory are ours,
+            // anything else (including paths that try to escape) is ignored.
+            let name = match entry.enclosed_name() {
+                Some(name) if entry.is_file() => name,
+                _ => continue,
+            };
+            if name == Path::new(INDEX_FILE_NAME) {
+                let mut bytes = Vec::new();
+                entry.read_to_end(&mut bytes).map_err(DataError::io(src))?;
+                index = Some(bytes);
+                continue;
+            }
+            if name.parent() != Some(Path::new(ZIP_FILES_DIR_NAME)) {
+                continue;
+            }
+
+            let dest = files_dir.join(name.file_name().unwrap());
+            let mut dest_file = std::fs::File::create(&dest).map_err(DataError::io(&dest))?;
+            std::io::copy(&mut entry, &mut dest_file).map_err(DataError::io(&dest))?;
+        }
+
+        if let Some(index) = index {
+            write_index(save_dir, &index)?;
+        }
+        Data::load(save_dir, files_dir)
+    }
+
+    /// Writes a row for every file to a CSV file at `dest`, sorted by id, for reviewing in a
+    /// spreadsheet. The columns are id, title, extension, size in bytes, when the file was
+    /// added (in seconds since the Unix epoch), and the system tags, separated by `;`.
+    pub fn export_csv(&self, dest: &Path) -> Result<(), DataError> {
+        let mut csv = String::from("id,title,extension,size_bytes,created_at,system_tags\n");
+        for id in self.list_files_sorted(SortKey::IdAsc) {
+            let file = self.files.get(id).unwrap();
+            let created_at = file
+                .created_at()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs());
+            let mut tags: Vec<&str> = file.system_tags().iter().map(|tag| tag.to_str()).collect();
+            tags.sort();
+
+            csv.push_str(&format!(
+                "{},{},{},{},{},{}\n",
+                id,
+                csv_field(file.title()),
+                file.extension(),
+                file.size_bytes(),
+                created_at,
+                tags.join(";")
+            ));
+        }
+
+        std::fs::write(dest, csv).map_err(DataError::io(dest))
+    }
+
+    /// Registers a callback that is called after every change to the stores.
+    /// Replaces the previous callback, if there was one.
+    pub fn set_on_change(&mut self, callback: Box<dyn FnMut(StoreEvent)>) {
+        self.on_change = Some(callback);
+    }
+
+    fn notify(&mut self, event: StoreEvent) {
+        if let Some(callback) = &mut self.on_change {
+            callback(event);
+        }
     }
 
     /// Adds a new file from disk. Copies it over to the file directory.
     /// Will return an error if something goes wrong during copy,
     /// or if the file extension is not one we can deal with.
-    pub fn add_file_from_disk(&mut self, title: &str, file: &Path) -> Result<FileId> {
-        let extension = KnownExtension::from_path(file).context("Extension is not known.")?;
-        let (file_id, dest) = self.files.new_file(title, extension);
+    /// Images are decoded to detect system tags, failing to do so is not an error.
+    /// When a file with the exact same contents is already stored, nothing is copied
+    /// and the id of the existing file is returned.
+    pub fn add_file_from_disk(&mut self, title: &str, file: &Path) -> Result<FileId, DataError> {
+        self.import_file(title, file).map(|(id, _)| id)
+    }
+
+    /// Like `add_file_from_disk`, but also returns why the image properties could not be read,
+    /// if they couldn't.
+    fn import_file(
+        &mut self,
+        title: &str,
+        file: &Path,
+    ) -> Result<(FileId, Option<DataError>), DataError> {
+        checked_title(title)?;
+        let extension = self.checked_extension(file)?;
+
+        let hash = content_hash::hash_file(file).map_err(DataError::io(file))?;
+        if let Some(existing) = self.files.find_by_content_hash(&hash) {
+            return Ok((existing, None));
+        }
+
+        self.backend
+            .create_dir_all(&self.files_dir)
+            .map_err(DataError::io(&self.files_dir))?;
+        let (file_id, _) = self.files.new_file(title, extension)?;
+        let undecoded = self.copy_into_files_dir(file_id, &hash, file)?;
+        Ok((file_id, undecoded))
+    }
+
+    /// Adds a file under the given id, instead of handing out a new one.
+    /// Meant for migrating from elsewhere, where the ids have to stay the same.
+    /// Unlike `add_file_from_disk`, a file with the same contents is added again.
+    /// Will return an error if the id is already taken, see `FileStore::insert_with_id`.
+    pub fn add_file_with_id(
+        &mut self,
+        id: FileId,
+        title: &str,
+        file: &Path,
+    ) -> Result<(), DataError> {
+        checked_title(title)?;
+        let extension = self.checked_extension(file)?;
+        let hash = content_hash::hash_file(file).map_err(DataError::io(file))?;
+
+        self.backend
+            .create_dir_all(&self.files_dir)
+            .map_err(DataError::io(&self.files_dir))?;
+        self.files.insert_with_id(id, title, extension)?;
+        self.copy_into_files_dir(id, &hash, file).map(|_| ())
+    }
+
+    /// The extension of a file that is about to be added.
+    /// Will return an error if we can't deal with the extension, or, when verifying images,
+    /// if the contents don't match it.
+    fn checked_extension(&self, file: &Path) -> Result<KnownExtension, DataError> {
+        let extension = KnownExtension::from_path(file).ok_or_else(|| {
+            DataError::UnknownExtension(
+                file.extension()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+            )
+        })?;
+
+        #[cfg(feature = "image")]
+        if self.verify_images && extension.is_image() && !images::is_valid_file(file, &extension) {
+            return Err(DataError::NotAnImage(file.to_path_buf()));
+        }
+
+        Ok(extension)
+    }
+
+    /// Copies the file for a just created entry into the files directory.
+    /// When that fails, the entry is removed again.
+    /// Returns why the image properties could not be read, see `finish_copy`.
+    fn copy_into_files_dir(
+        &mut self,
+        file_id: FileId,
+        hash: &str,
+        file: &Path,
+    ) -> Result<Option<DataError>, DataError> {
+        self.files.set_content_hash(file_id, hash);
+        self.files.set_original_path(file_id, file);
+        let full_dest = self
+            .file_path(file_id)
+            .ok_or(DataError::FileNotFound(file_id))?;
+        self.check_destination(file_id, &full_dest, hash)?;
+
+        let copied = self.backend.copy_from_disk(file, &full_dest);
+        self.finish_copy(file_id, file, full_dest, copied)
+    }
+
+    /// Like `add_file_from_disk`, but without blocking on reading and copying the file.
+    /// Images are still decoded on the calling thread, when the image feature is on.
+    #[cfg(feature = "tokio")]
+    pub async fn add_file_from_disk_async(
+        &mut self,
+        title: &str,
+        file: &Path,
+    ) -> Result<FileId, DataError> {
+        checked_title(title)?;
+        let extension = self.checked_extension(file)?;
+
+        let to_hash = file.to_path_buf();
+        let hash = tokio::task::spawn_blocking(move || content_hash::hash_file(&to_hash))
+            .await
+            .map_err(std::io::Error::other)
+            .and_then(|hashed| hashed)
+            .map_err(DataError::io(file))?;
+        if let Some(existing) = self.files.find_by_content_hash(&hash) {
+            return Ok(existing);
+        }
+
+        self.backend
+            .create_dir_all(&self.files_dir)
+            .map_err(DataError::io(&self.files_dir))?;
+        let (file_id, dest) = self.files.new_file(title, extension)?;
+        self.files.set_content_hash(file_id, &hash);
+        self.files.set_original_path(file_id, file);
         let full_dest = self.files_dir.join(dest);
+        self.check_destination(file_id, &full_dest, &hash)?;
 
-        match std::fs::copy(file, &full_dest) {
-            Ok(_) => {}
+        let copied = if self.backend.is_on_disk() {
+            tokio::fs::copy(file, &full_dest).await
+        } else {
+            // Only the reading can be done without blocking, the backend is not async.
+            let read = tokio::fs::read(file).await;
+            read.and_then(|bytes| {
+                self.backend
+                    .write(&full_dest, &bytes)
+                    .map(|_| bytes.len() as u64)
+            })
+        };
+        self.finish_copy(file_id, file, full_dest, copied)?;
+        Ok(file_id)
+    }
+
+    /// Makes sure that adding a file does not overwrite a different file that was left behind
+    /// in the files directory, like after a crash before the index was saved.
+    /// If there is one, the just created entry is removed again.
+    fn check_destination(
+        &mut self,
+        file_id: FileId,
+        full_dest: &Path,
+        hash: &str,
+    ) -> Result<(), DataError> {
+        let free = self.check_free(full_dest, hash);
+        if free.is_err() {
+            self.files.remove(&file_id);
+        }
+        free
+    }
+
+    /// Whether contents with the given hash can be written to `full_dest`: there is nothing
+    /// there yet, or the exact same contents are. Changes nothing, see `check_destination`.
+    fn check_free(&self, full_dest: &Path, hash: &str) -> Result<(), DataError> {
+        if !self.backend.is_file(full_dest) {
+            return Ok(());
+        }
+
+        match self.backend.content_hash(full_dest) {
+            Ok(existing) if existing == hash => Ok(()),
+            Ok(_) => Err(DataError::StaleFile(full_dest.to_path_buf())),
+            Err(e) => Err(DataError::io(full_dest)(e)),
+        }
+    }
+
+    /// Completes adding a file, after it has been copied to `full_dest`.
+    /// When the copy failed, the entry is removed again.
+    /// Returns why the image properties could not be read, if they couldn't.
+    fn finish_copy(
+        &mut self,
+        file_id: FileId,
+        file: &Path,
+        full_dest: PathBuf,
+        copied: std::io::Result<u64>,
+    ) -> Result<Option<DataError>, DataError> {
+        match copied {
+            Ok(size_bytes) => {
+                self.files.set_size_bytes(file_id, size_bytes);
+            }
             Err(e) => {
                 // The file is not actually in the save folder.
                 // Make sure we don't leave an orphaned reference in the storage.
                 self.files.remove(&file_id);
-                return Err(e).with_context(|| {
-                    format!(
-                        "Could not copy asset \"{}\" to the file storage at \"{}\"",
-                        file.display(),
-                        full_dest.display()
-                    )
+                return Err(DataError::CopyFailed {
+                    src: file.to_path_buf(),
+                    dest: full_dest,
+                    source: e,
                 });
             }
         }
 
+        #[cfg(feature = "image")]
+        let undecoded = self.detect_image_properties(file_id, &full_dest).err();
+        #[cfg(not(feature = "image"))]
+        let undecoded = None;
+
+        self.notify(StoreEvent::FileAdded(file_id));
+        Ok(undecoded)
+    }
+
+    /// Adds all the files in a directory with a known extension, see `add_file_from_disk`.
+    /// The file stems are used as titles. Files with other extensions are skipped.
+    /// Files are imported in order of their path.
+    /// Will only return an error if a directory could not be read.
+    pub fn import_directory(
+        &mut self,
+        dir: &Path,
+        recursive: bool,
+    ) -> Result<ImportSummary, DataError> {
+        let mut summary = ImportSummary {
+            imported: Vec::new(),
+            failed: Vec::new(),
+            undecoded: Vec::new(),
+        };
+
+        let known_files = Self::list_files(dir, recursive)?
+            .into_iter()
+            .filter(|path| KnownExtension::from_path(path).is_some());
+        for path in known_files {
+            let title = path.file_stem().unwrap_or_default().to_string_lossy();
+            let result = self.import_file(&title, &path);
+            summary.record(path, result);
+        }
+
+        Ok(summary)
+    }
+
+    /// Like `import_directory`, but reads and copies the files on multiple threads.
+    /// The stores are only changed from the calling thread, in order of the paths,
+    /// so the files get the same ids as with `import_directory`.
+    /// When the backend is not on disk, the files are copied into it on the calling thread.
+    #[cfg(feature = "rayon")]
+    pub fn import_directory_parallel(
+        &mut self,
+        dir: &Path,
+        recursive: bool,
+    ) -> Result<ImportSummary, DataError> {
+        use rayon::prelude::*;
+
+        let paths: Vec<PathBuf> = Self::list_files(dir, recursive)?
+            .into_iter()
+            .filter(|path| KnownExtension::from_path(path).is_some())
+            .collect();
+        let hashes: Vec<std::io::Result<String>> = paths
+            .par_iter()
+            .map(|path| content_hash::hash_file(path))
+            .collect();
+
+        // Before handing out ids, so a failure here leaves no entries without a file behind.
+        self.backend
+            .create_dir_all(&self.files_dir)
+            .map_err(DataError::io(&self.files_dir))?;
+
+        // Hand out the ids, without copying anything yet.
+        let mut steps = Vec::with_capacity(paths.len());
+        let mut new_hashes: HashMap<String, usize> = HashMap::new();
+        for (i, (path, hash)) in paths.iter().zip(hashes).enumerate() {
+            let title = path.file_stem().unwrap_or_default().to_string_lossy();
+            let checked = checked_title(&title)
+                .and_then(|_| self.checked_extension(path))
+                .and_then(|extension| Ok((extension, hash.map_err(DataError::io(path))?)));
+            let step = match checked {
+                Err(e) => ImportStep::Done(Err(e)),
+                Ok((_, hash)) if new_hashes.contains_key(&hash) => {
+                    ImportStep::SameAs(new_hashes[&hash])
+                }
+                Ok((extension, hash)) => match self.files.find_by_content_hash(&hash) {
+                    Some(existing) => ImportStep::Done(Ok(existing)),
+                    // Like running out of ids, which should not leave the earlier ids behind.
+                    None => match self.files.new_file(&title, extension) {
+                        Err(e) => ImportStep::Done(Err(e.into())),
+                        Ok((id, dest)) => {
+                            self.files.set_content_hash(id, &hash);
+                            self.files.set_original_path(id, path);
+                            let dest = self.files_dir.join(dest);
+                            match self.check_destination(id, &dest, &hash) {
+                                Ok(()) => {
+                                    new_hashes.insert(hash, i);
+                                    ImportStep::Copy(id, dest)
+                                }
+                                Err(e) => ImportStep::Done(Err(e)),
+                            }
+                        }
+                    },
+                },
+            };
+            steps.push(step);
+        }
+
+        let jobs: Vec<(usize, &Path)> = steps
+            .iter()
+            .enumerate()
+            .filter_map(|(i, step)| match step {
+                ImportStep::Copy(_, dest) => Some((i, dest.as_path())),
+                _ => None,
+            })
+            .collect();
+        let mut copies: Vec<Option<std::io::Result<u64>>> =
+            (0..paths.len()).map(|_| None).collect();
+        let copied: Vec<(usize, std::io::Result<u64>)> = if self.backend.is_on_disk() {
+            jobs.par_iter()
+                .map(|&(i, dest)| (i, std::fs::copy(&paths[i], dest)))
+                .collect()
+        } else {
+            jobs.iter()
+                .map(|&(i, dest)| (i, self.backend.copy_from_disk(&paths[i], dest)))
+                .collect()
+        };
+        for (i, result) in copied {
+            copies[i] = Some(result);
+        }
+
+        // Register the results, in order.
+        let mut summary = ImportSummary {
+            imported: Vec::new(),
+            failed: Vec::new(),
+            undecoded: Vec::new(),
+        };
+        let mut ids: Vec<Option<FileId>> = Vec::with_capacity(paths.len());
+        for (i, step) in steps.into_iter().enumerate() {
+            let path = &paths[i];
+            let result = match step {
+                ImportStep::Done(result) => result.map(|id| (id, None)),
+                ImportStep::Copy(id, dest) => {
+                    let copied = copies[i].take().unwrap();
+                    self.finish_copy(id, path, dest, copied)
+                        .map(|undecoded| (id, undecoded))
+                }
+                ImportStep::SameAs(first) => match ids[first] {
+                    Some(id) => Ok((id, None)),
+                    // The first one failed, so this one has to be added after all.
+                    None => {
+                        let title = path.file_stem().unwrap_or_default().to_string_lossy();
+                        self.import_file(&title, path)
+                    }
+                },
+            };
+
+            ids.push(result.as_ref().ok().map(|&(id, _)| id));
+            summary.record(path.clone(), result);
+        }
+
+        Ok(summary)
+    }
+
+    /// Finds out what `import_directory` would do, without changing anything.
+    /// Will only return an error if a directory could not be read.
+    pub fn preview_directory(
+        &self,
+        dir: &Path,
+        recursive: bool,
+    ) -> Result<ImportPreview, DataError> {
+        let mut preview = ImportPreview {
+            new: Vec::new(),
+            skipped: Vec::new(),
+        };
+        let mut new_hashes: HashMap<String, PathBuf> = HashMap::new();
+
+        for path in Self::list_files(dir, recursive)? {
+            if KnownExtension::from_path(&path).is_none() {
+                preview.skipped.push((path, SkipReason::UnknownExtension));
+                continue;
+            }
+
+            let hash = match self
+                .checked_extension(&path)
+                .and_then(|_| content_hash::hash_file(&path).map_err(DataError::io(&path)))
+            {
+                Ok(hash) => hash,
+                Err(e) => {
+                    preview.skipped.push((path, SkipReason::Failed(e)));
+                    continue;
+                }
+            };
+
+            if let Some(existing) = self.files.find_by_content_hash(&hash) {
+                preview
+                    .skipped
+                    .push((path, SkipReason::AlreadyStored(existing)));
+            } else if let Some(earlier) = new_hashes.get(&hash) {
+                let reason = SkipReason::SameAs(earlier.clone());
+                preview.skipped.push((path, reason));
+            } else {
+                new_hashes.insert(hash, path.clone());
+                preview.new.push(path);
+            }
+        }
+
+        Ok(preview)
+    }
+
+    /// All the files in a directory, sorted by path.
+    fn list_files(dir: &Path, recursive: bool) -> Result<Vec<PathBuf>, DataError> {
+        let mut files = Vec::new();
+
+        for entry in std::fs::read_dir(dir).map_err(DataError::io(dir))? {
+            let path = entry.map_err(DataError::io(dir))?.path();
+
+            if path.is_dir() {
+                if recursive {
+                    files.append(&mut Self::list_files(&path, recursive)?);
+                }
+            } else {
+                files.push(path);
+            }
+        }
+
+        files.sort();
+        Ok(files)
+    }
+
+    /// Adds a new file from memory, by writing it to the file directory.
+    /// Will return an error if something goes wrong during writing.
+    /// Just like with `add_file_from_disk`, files that are already stored are not stored again.
+    pub fn add_file_from_bytes(
+        &mut self,
+        title: &str,
+        extension: KnownExtension,
+        bytes: &[u8],
+    ) -> Result<FileId, DataError> {
+        checked_title(title)?;
+        #[cfg(feature = "image")]
+        if self.verify_images && extension.is_image() && !images::is_valid_bytes(bytes, &extension)
+        {
+            return Err(DataError::InvalidImageContent(title.to_string()));
+        }
+
+        let hash = content_hash::hash_bytes(bytes);
+        if let Some(existing) = self.files.find_by_content_hash(&hash) {
+            return Ok(existing);
+        }
+
+        self.backend
+            .create_dir_all(&self.files_dir)
+            .map_err(DataError::io(&self.files_dir))?;
+        let (file_id, dest) = self.files.new_file(title, extension)?;
+        self.files.set_content_hash(file_id, &hash);
+        let full_dest = self.files_dir.join(dest);
+        self.check_destination(file_id, &full_dest, &hash)?;
+
+        match self.backend.write(&full_dest, bytes) {
+            Ok(_) => {
+                self.files.set_size_bytes(file_id, bytes.len() as u64);
+            }
+            Err(e) => {
+                // Make sure we don't leave an orphaned reference in the storage.
+                self.files.remove(&file_id);
+                return Err(DataError::io(&full_dest)(e));
+            }
+        }
+
+        // The file is stored either way, so not being able to decode it is not an error.
+        #[cfg(feature = "image")]
+        let _ = self.detect_image_properties(file_id, &full_dest);
+
+        self.notify(StoreEvent::FileAdded(file_id));
         Ok(file_id)
     }
 
-    pub fn file_count(&self) -> usize {
-        self.files.count()
+    /// Like `add_file_from_bytes`, but the bytes are streamed straight to the files directory,
+    /// so they don't all have to be in memory at once. Useful for uploads.
+    /// The hash is only known after the whole file has been written, so an existing file
+    /// in the way is always reported as stale, and a duplicate is written and then removed again.
+    pub fn add_file_from_reader(
+        &mut self,
+        title: &str,
+        extension: KnownExtension,
+        reader: impl Read,
+    ) -> Result<FileId, DataError> {
+        checked_title(title)?;
+        self.backend
+            .create_dir_all(&self.files_dir)
+            .map_err(DataError::io(&self.files_dir))?;
+        let (file_id, dest) = self.files.new_file(title, extension)?;
+        let full_dest = self.files_dir.join(dest);
+        if self.backend.is_file(&full_dest) {
+            self.files.remove(&file_id);
+            return Err(DataError::StaleFile(full_dest));
+        }
+
+        let mut hashing = content_hash::HashingReader::new(reader);
+        if let Err(e) = self.backend.write_from_reader(&full_dest, &mut hashing) {
+            // Don't leave a partial file or an orphaned reference behind.
+            let _ = self.backend.remove(&full_dest);
+            self.files.remove(&file_id);
+            return Err(DataError::io(&full_dest)(e));
+        }
+        let (hash, size_bytes) = hashing.finish();
+
+        if let Some(existing) = self.files.find_by_content_hash(&hash) {
+            self.files.remove(&file_id);
+            self.backend
+                .remove(&full_dest)
+                .map_err(DataError::io(&full_dest))?;
+            return Ok(existing);
+        }
+
+        #[cfg(feature = "image")]
+        if self.verify_images && !self.is_valid_stored_image(file_id, &full_dest) {
+            self.files.remove(&file_id);
+            self.backend
+                .remove(&full_dest)
+                .map_err(DataError::io(&full_dest))?;
+            return Err(DataError::InvalidImageContent(title.to_string()));
+        }
+
+        self.files.set_content_hash(file_id, &hash);
+        self.files.set_size_bytes(file_id, size_bytes);
+
+        // The file is stored either way, so not being able to decode it is not an error.
+        #[cfg(feature = "image")]
+        let _ = self.detect_image_properties(file_id, &full_dest);
+
+        self.notify(StoreEvent::FileAdded(file_id));
+        Ok(file_id)
     }
 
-    pub fn file_iter(&self) -> Iter<FileId, File> {
-        self.files.iter()
+    /// Whether the stored file can be decoded. Files that are not images are always valid.
+    #[cfg(feature = "image")]
+    fn is_valid_stored_image(&self, id: FileId, path: &Path) -> bool {
+        match self.files.get(id) {
+            Some(file) if file.extension().is_image() => self
+                .backend
+                .read(path)
+                .is_ok_and(|bytes| images::is_valid_bytes(&bytes, file.extension())),
+            _ => true,
+        }
     }
 
-    pub fn get_file_info(&self, id: FileId) -> Option<&File> {
-        self.files.get(id)
+    /// Decodes the stored image, and records whatever we find, like the dimensions and tags.
+    /// Files that are not images are left alone.
+    /// Stops at the first property that can't be read, and returns why.
+    #[cfg(feature = "image")]
+    fn detect_image_properties(&mut self, id: FileId, path: &Path) -> Result<(), DataError> {
+        let is_image = self
+            .files
+            .get(id)
+            .is_some_and(|file| file.extension().is_image());
+        if !is_image {
+            return Ok(());
+        }
+
+        let bytes = self.backend.read(path).map_err(DataError::io(path))?;
+        let undecodable = |source| DataError::Image {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        let dimensions = images::dimensions_bytes(&bytes).map_err(undecodable)?;
+        self.files.set_dimensions(id, dimensions);
+
+        if images::has_transparency_bytes(&bytes).map_err(undecodable)? {
+            self.files.add_system_tag(id, SystemTag::Transparent);
+        }
+
+        #[cfg(feature = "phash")]
+        {
+            let hash = images::difference_hash_bytes(&bytes).map_err(undecodable)?;
+            self.files.set_perceptual_hash(id, hash);
+        }
+
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use tempfile;
-    use tempfile::TempDir;
+    /// Finds the images that look like the given one, like resized or re-encoded copies.
+    /// `max_distance` is how many bits of the perceptual hashes may differ, out of 64.
+    /// The given file itself is left out. The ids are sorted.
+    #[cfg(feature = "phash")]
+    pub fn find_similar(&self, id: FileId, max_distance: u32) -> Vec<FileId> {
+        let hash = match self.files.get(id).and_then(|file| file.perceptual_hash()) {
+            Some(hash) => hash,
+            None => return Vec::new(),
+        };
 
-    const TEST_FILES_PATH: &str = "tests/files";
+        let mut similar: Vec<FileId> = self
+            .files
+            .iter()
+            .filter(|(other, file)| {
+                **other != id
+                    && file.perceptual_hash().is_some_and(|other_hash| {
+                        images::hash_distance(hash, other_hash) <= max_distance
+                    })
+            })
+            .map(|(other, _)| *other)
+            .collect();
+        similar.sort();
+        similar
+    }
 
-    #[test]
-    fn check_data_initialization() {
-        // Setup a temporary directory for the test.
-        let (_, save_dir, file_dir) = setup_temp_directory();
+    /// Removes a file from the store, and deletes it from the file directory.
+    /// Will return an error if the file is not known, if an asset still refers to it,
+    /// or if it could not be deleted.
+    /// A file that was already gone from disk is not an error, the store entry is still removed.
+    pub fn remove_file(&mut self, id: FileId) -> Result<(), DataError> {
+        let path = self.file_path(id).ok_or(DataError::FileNotFound(id))?;
 
-        // Directories should at the moment not be there.
-        assert!(!save_dir.exists());
-        assert!(!file_dir.exists());
+        let users = self.assets.assets_using_file(id);
+        if !users.is_empty() {
+            return Err(DataError::FileInUse {
+                file: id,
+                assets: users,
+            });
+        }
 
-        // Initialize the data.
-        let data = Data::new(&save_dir, &file_dir);
+        match self.backend.remove(&path) {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                // Keep the store entry, the file is still there.
+                return Err(DataError::io(&path)(e));
+            }
+        }
 
-        // Now they should be there.
-        assert!(save_dir.exists());
-        assert!(file_dir.exists());
+        self.files.remove(&id);
+        self.notify(StoreEvent::FileRemoved(id));
+        Ok(())
     }
 
-    #[test]
-    fn add_assets() -> Result<()> {
-        // Setup a temporary directory for the test.
-        let (_, save_dir, file_dir) = setup_temp_directory();
-        // Initialize the data.
-        let mut data = Data::new(&save_dir, &file_dir).unwrap();
+    /// Removes each of the files, see `remove_file`, like for a multi-selection.
+    /// Files that can't be removed, for example because an asset still refers to them,
+    /// are reported and skipped.
+    pub fn remove_files(&mut self, ids: &[FileId]) -> RemoveReport {
+        let mut report = RemoveReport {
+            removed: Vec::new(),
+            failed: Vec::new(),
+        };
 
-        let test_files = Path::new(TEST_FILES_PATH);
+        for &id in ids {
+            match self.remove_file(id) {
+                Ok(_) => report.removed.push(id),
+                Err(e) => report.failed.push((id, e)),
+            }
+        }
+        report
+    }
 
-        let title = "Testing title";
+    /// Removes all the files, see `remove_file`.
+    /// Will return an error, without removing anything, if an asset still refers to a file.
+    pub fn clear_files(&mut self) -> Result<(), DataError> {
+        let ids: Vec<FileId> = self.files.ids().copied().collect();
 
-        let id = data.add_file_from_disk(title, &test_files.join(Path::new("swords/tall.png")))?;
+        for &id in &ids {
+            let users = self.assets.assets_using_file(id);
+            if !users.is_empty() {
+                return Err(DataError::FileInUse {
+                    file: id,
+                    assets: users,
+                });
+            }
+        }
 
-        // Check if it has been created properly.
-        assert_eq!(data.file_count(), 1);
-        let asset = data.get_file_info(id).unwrap();
-        assert_eq!(asset.title(), title);
+        for id in ids {
+            self.remove_file(id)?;
+        }
+        Ok(())
+    }
 
-        // TODO: Check if the file can be retrieved as well.
+    /// Removes all the files for which `keep` returns false, from the store and from disk.
+    /// Will return an error, without removing anything, if an asset still refers to one
+    /// of those files. See `remove_file` for the other errors.
+    pub fn retain_files<F>(&mut self, mut keep: F) -> Result<(), DataError>
+    where
+        F: FnMut(&FileId, &File) -> bool,
+    {
+        let mut ids: Vec<FileId> = self
+            .files
+            .iter()
+            .filter(|(id, file)| !keep(id, file))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort();
+
+        for &id in &ids {
+            let users = self.assets.assets_using_file(id);
+            if !users.is_empty() {
+                return Err(DataError::FileInUse {
+                    file: id,
+                    assets: users,
+                });
+            }
+        }
+
+        for id in ids {
+            self.remove_file(id)?;
+        }
+        Ok(())
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.files.count()
+    }
+
+    /// Moves all the stored files to a new files directory, and keeps using that one from now on.
+    /// When moving one of the files fails, the files that were already moved are moved back.
+    /// The old directory is removed when nothing else is left in it.
+    pub fn move_files_dir(&mut self, new_dir: &Path) -> Result<(), DataError> {
+        self.backend
+            .create_dir_all(new_dir)
+            .map_err(DataError::io(new_dir))?;
+
+        let names: Vec<PathBuf> = self.files.values().map(|file| file.file_name()).collect();
+        for (i, name) in names.iter().enumerate() {
+            let from = self.files_dir.join(name);
+            if let Err(e) = self.backend.rename(&from, &new_dir.join(name)) {
+                for moved in &names[..i] {
+                    // Best effort, we are already handling an error.
+                    let _ = self
+                        .backend
+                        .rename(&new_dir.join(moved), &self.files_dir.join(moved));
+                }
+                return Err(DataError::io(&from)(e));
+            }
+        }
+
+        // Fails when there is something else in there, which we should leave alone.
+        let _ = self.backend.remove_dir(&self.files_dir);
+        self.files_dir = new_dir.to_path_buf();
+        Ok(())
+    }
+
+    /// Compares the files directory with the file store. Files that are missing from their
+    /// expected place are looked for by content hash among the unexpected files.
+    /// When `fix` is true, the ones that are found are renamed to where they should be,
+    /// otherwise nothing is changed and the report says what would be fixed.
+    /// Everything in the report is sorted.
+    pub fn reconcile_disk(&mut self, fix: bool) -> Result<ReconcileReport, DataError> {
+        let mut expected: HashMap<PathBuf, FileId> = self
+            .files
+            .iter()
+            .map(|(id, file)| (file.file_name(), *id))
+            .collect();
+
+        let mut strays = Vec::new();
+        let stored = self
+            .backend
+            .list(&self.files_dir)
+            .map_err(DataError::io(&self.files_dir))?;
+        for path in stored {
+            let name = PathBuf::from(path.file_name().unwrap_or_default());
+            if expected.remove(&name).is_none() {
+                strays.push(path);
+            }
+        }
+        strays.sort();
+
+        let mut report = ReconcileReport::default();
+        let mut missing: Vec<FileId> = expected.into_values().collect();
+        missing.sort();
+        for path in strays {
+            let hash = self
+                .backend
+                .content_hash(&path)
+                .map_err(DataError::io(&path))?;
+            let found = missing
+                .iter()
+                .position(|&id| self.files.get(id).unwrap().content_hash() == Some(&hash));
+
+            match found {
+                Some(i) => {
+                    let id = missing.remove(i);
+                    if fix {
+                        let dest = self.file_path(id).unwrap();
+                        self.backend
+                            .rename(&path, &dest)
+                            .map_err(DataError::io(&path))?;
+                    }
+                    report.fixed.push((path, id));
+                }
+                None => report.unexpected.push(path),
+            }
+        }
+        report.missing = missing;
+
+        Ok(report)
+    }
+
+    /// How much disk space all the files take up together.
+    pub fn total_size_bytes(&self) -> u64 {
+        self.files.values().map(|file| file.size_bytes()).sum()
+    }
+
+    pub fn file_iter(&self) -> StoreIter<'_, FileId, File> {
+        self.files.iter()
+    }
+
+    pub fn get_file_info(&self, id: FileId) -> Option<&File> {
+        self.files.get(id)
+    }
+
+    /// Changes the title of a file, see `FileStore::set_title`.
+    pub fn rename_file(&mut self, id: FileId, title: &str) -> Result<(), DataError> {
+        if !self.files.contains(id) {
+            return Err(DataError::FileNotFound(id));
+        }
+        checked_title(title)?;
+        self.files.set_title(id, title);
+        Ok(())
+    }
+
+    /// How many files there are of each extension, see `FileStore::count_by_extension`.
+    pub fn count_by_extension(&self) -> HashMap<String, usize> {
+        self.files.count_by_extension()
+    }
+
+    /// All the files with the given extension, see `FileStore::iter_by_extension`.
+    pub fn files_with_extension<'a>(
+        &'a self,
+        extension: &'a KnownExtension,
+    ) -> impl Iterator<Item = (&'a FileId, &'a File)> + 'a {
+        self.files.iter_by_extension(extension)
+    }
+
+    /// Finds files by system tag, see `FileStore::find_by_system_tag`.
+    pub fn files_with_system_tag(&self, tag: SystemTag) -> Vec<FileId> {
+        self.files.find_by_system_tag(tag)
+    }
+
+    /// Finds files by title, see `FileStore::search_titles`.
+    pub fn search_files(&self, query: &str) -> Vec<FileId> {
+        self.files.search_titles(query)
+    }
+
+    /// All the files, in the given order. Files that are equal for the key are sorted by id,
+    /// so the order is always the same.
+    pub fn list_files_sorted(&self, key: SortKey) -> Vec<FileId> {
+        let mut files: Vec<(&FileId, &File)> = self.files.iter().collect();
+        files.sort_by(|(a_id, a), (b_id, b)| {
+            let order = match key {
+                SortKey::TitleAsc => a.title().to_lowercase().cmp(&b.title().to_lowercase()),
+                SortKey::TitleDesc => b.title().to_lowercase().cmp(&a.title().to_lowercase()),
+                SortKey::IdAsc => a_id.cmp(b_id),
+                SortKey::SizeDesc => b.size_bytes().cmp(&a.size_bytes()),
+                SortKey::CreatedAtAsc => a.created_at().cmp(&b.created_at()),
+            };
+            order.then(a_id.cmp(b_id))
+        });
+
+        files.into_iter().map(|(id, _)| *id).collect()
+    }
+
+    /// The file together with where it is stored, see `Data::file_path`.
+    /// Returns None when the file is not known.
+    pub fn file_with_path(&self, id: FileId) -> Option<(&File, PathBuf)> {
+        self.files
+            .get(id)
+            .map(|file| (file, self.files_dir.join(file.file_name())))
+    }
+
+    /// Where the file is stored on disk.
+    /// Returns None when the file is not known.
+    pub fn file_path(&self, id: FileId) -> Option<PathBuf> {
+        self.files
+            .get(id)
+            .map(|file| self.files_dir.join(file.file_name()))
+    }
+
+    /// Creates a thumbnail of an image file, with its longest side being `max_dim` pixels.
+    /// Thumbnails are cached in the save directory, and only regenerated when the size is
+    /// different or when the file is newer than the thumbnail.
+    /// Jpeg thumbnails of different qualities are cached separately.
+    /// Returns the path of the thumbnail.
+    /// Will return an error if the format is not a raster format, or the quality is out of range.
+    #[cfg(feature = "image")]
+    pub fn generate_thumbnail(
+        &self,
+        id: FileId,
+        options: &ThumbnailOptions,
+    ) -> Result<PathBuf, DataError> {
+        let format = &options.format;
+        if !format.is_image() {
+            return Err(DataError::NotARasterFormat(format.to_str()));
+        }
+        if !(1..=100).contains(&options.jpeg_quality) {
+            return Err(DataError::InvalidJpegQuality(options.jpeg_quality));
+        }
+        let source = self.file_path(id).ok_or(DataError::FileNotFound(id))?;
+
+        std::fs::create_dir_all(&self.thumbnails_dir)
+            .map_err(DataError::io(&self.thumbnails_dir))?;
+        let name = match format {
+            KnownExtension::Jpeg => format!("{}_thumb_q{}", id, options.jpeg_quality),
+            _ => format!("{}_thumb", id),
+        };
+        let thumbnail = self
+            .thumbnails_dir
+            .join(name)
+            .with_extension(format.to_str());
+
+        if !self.is_thumbnail_up_to_date(&source, &thumbnail, options.max_dim)? {
+            let bytes = self.backend.read(&source).map_err(DataError::io(&source))?;
+            images::write_thumbnail(
+                &bytes,
+                &thumbnail,
+                options.max_dim,
+                format,
+                options.jpeg_quality,
+            )
+            .map_err(|source| DataError::Image {
+                path: thumbnail.clone(),
+                source,
+            })?;
+        }
+
+        Ok(thumbnail)
+    }
+
+    /// Converts a stored image to a different format, like a png to a jpeg to save space.
+    /// The converted file replaces the old one, and its properties, like the tags and the
+    /// content hash, are recorded again. Converting to the format it already has does nothing.
+    /// Will return an error if the file is not known, can't be decoded,
+    /// or if the target is not a raster format.
+    /// Once the converted file is written, removing the old one is best effort. When that fails,
+    /// it is left behind as an unexpected file, see `reconcile_disk`.
+    #[cfg(feature = "image")]
+    pub fn convert_file(&mut self, id: FileId, to: KnownExtension) -> Result<(), DataError> {
+        if !to.is_image() {
+            return Err(DataError::NotARasterFormat(to.to_str()));
+        }
+        let file = self.files.get(id).ok_or(DataError::FileNotFound(id))?;
+        if file.extension() == &to {
+            return Ok(());
+        }
+
+        let old_path = self.files_dir.join(file.file_name());
+        let new_path = self
+            .files_dir
+            .join(file.file_name().with_extension(to.to_str()));
+        let bytes = self
+            .backend
+            .read(&old_path)
+            .map_err(DataError::io(&old_path))?;
+        let converted =
+            images::convert_bytes(&bytes, &to, CONVERTED_JPEG_QUALITY).map_err(|source| {
+                DataError::Image {
+                    path: old_path.clone(),
+                    source,
+                }
+            })?;
+
+        let hash = content_hash::hash_bytes(&converted);
+        self.check_free(&new_path, &hash)?;
+        self.backend
+            .write(&new_path, &converted)
+            .map_err(DataError::io(&new_path))?;
+
+        self.files.set_extension(id, to);
+        self.files.set_content_hash(id, &hash);
+        self.files.set_size_bytes(id, converted.len() as u64);
+        // Whatever the new format keeps is found again. It was just encoded, so it decodes.
+        self.files.remove_system_tag(id, SystemTag::Transparent);
+        let _ = self.detect_image_properties(id, &new_path);
+
+        let _ = self.backend.remove(&old_path);
+        Ok(())
+    }
+
+    /// Whether the thumbnail exists, has the right size, and is not older than its source.
+    #[cfg(feature = "image")]
+    fn is_thumbnail_up_to_date(
+        &self,
+        source: &Path,
+        thumbnail: &Path,
+        max_dim: u32,
+    ) -> Result<bool, DataError> {
+        if !thumbnail.exists() {
+            return Ok(false);
+        }
+
+        let source_modified = self
+            .backend
+            .modified(source)
+            .map_err(DataError::io(source))?;
+        let thumbnail_modified = std::fs::metadata(thumbnail)
+            .and_then(|metadata| metadata.modified())
+            .map_err(DataError::io(thumbnail))?;
+        if source_modified > thumbnail_modified {
+            return Ok(false);
+        }
+
+        // A thumbnail we can't read is as good as no thumbnail.
+        Ok(match images::dimensions(thumbnail) {
+            Ok((width, height)) => width.max(height) == max_dim,
+            Err(_) => false,
+        })
+    }
+
+    /// Reads the contents of a stored file.
+    /// Will return an error if the file is not known, or if it can not be read from disk.
+    pub fn read_file_bytes(&self, id: FileId) -> Result<Vec<u8>, DataError> {
+        let path = self.file_path(id).ok_or(DataError::FileNotFound(id))?;
+
+        self.backend.read(&path).map_err(DataError::io(&path))
+    }
+
+    /// Groups the files that have the exact same contents, like ones added by `add_file_with_id`.
+    /// Files without a recorded hash are hashed now, files that can't be read are left out.
+    /// Every group has at least two files. The groups and the ids in them are sorted.
+    pub fn find_duplicate_files(&self) -> Vec<Vec<FileId>> {
+        let mut by_hash: HashMap<String, Vec<FileId>> = HashMap::new();
+        for (id, file) in self.files.iter() {
+            let hash = match file.content_hash() {
+                Some(hash) => hash.to_string(),
+                None => match self
+                    .backend
+                    .content_hash(&self.files_dir.join(file.file_name()))
+                {
+                    Ok(hash) => hash,
+                    Err(_) => continue,
+                },
+            };
+            by_hash.entry(hash).or_default().push(*id);
+        }
+
+        let mut groups: Vec<Vec<FileId>> = by_hash
+            .into_values()
+            .filter(|ids| ids.len() > 1)
+            .map(|mut ids| {
+                ids.sort();
+                ids
+            })
+            .collect();
+        groups.sort();
+        groups
+    }
+
+    /// Looks for assets that refer to unknown files, and for files that are gone from disk.
+    /// The issues are sorted by id, assets first.
+    pub fn check_integrity(&self) -> Vec<IntegrityIssue> {
+        let mut issues = Vec::new();
+
+        let mut asset_ids: Vec<AssetId> = self.assets.ids().copied().collect();
+        asset_ids.sort();
+        for asset in asset_ids {
+            for &file in self.assets.get(asset).unwrap().files() {
+                if !self.files.contains(file) {
+                    issues.push(IntegrityIssue::DanglingFile { asset, file });
+                }
+            }
+        }
+
+        let mut file_ids: Vec<FileId> = self.files.ids().copied().collect();
+        file_ids.sort();
+        for id in file_ids {
+            if !self
+                .file_path(id)
+                .is_some_and(|path| self.backend.is_file(&path))
+            {
+                issues.push(IntegrityIssue::MissingOnDisk(id));
+            }
+        }
+
+        issues
+    }
+
+    /// Hashes the stored file again, and compares that with the hash recorded when it was added,
+    /// to detect bit rot or changes from outside. Returns false when the contents changed.
+    /// Will return an error if the file is not known, has no recorded hash,
+    /// or is missing from the files directory.
+    pub fn verify_file(&self, id: FileId) -> Result<bool, DataError> {
+        let file = self.files.get(id).ok_or(DataError::FileNotFound(id))?;
+        let expected = file.content_hash().ok_or(DataError::NoContentHash(id))?;
+        let path = self.files_dir.join(file.file_name());
+        if !self.backend.is_file(&path) {
+            return Err(DataError::MissingOnDisk(id));
+        }
+
+        let hash = self
+            .backend
+            .content_hash(&path)
+            .map_err(DataError::io(&path))?;
+        Ok(hash == expected)
+    }
+
+    /// Creates a new asset that refers to already added files.
+    /// Will return an error if one of the files is not known, or if a file is given twice.
+    pub fn new_asset(&mut self, title: &str, files: &[FileId]) -> Result<AssetId, DataError> {
+        checked_title(title)?;
+        if let Some(&unknown) = files.iter().find(|&&file| !self.files.contains(file)) {
+            return Err(DataError::FileNotFound(unknown));
+        }
+
+        let id = self.assets.new_asset(title, files)?;
+        self.notify(StoreEvent::AssetAdded(id));
+        Ok(id)
+    }
+
+    /// Changes the title of an asset, see `AssetStore::set_title`.
+    pub fn rename_asset(&mut self, id: AssetId, title: &str) -> Result<(), DataError> {
+        if !self.assets.contains(id) {
+            return Err(DataError::AssetNotFound(id));
+        }
+        checked_title(title)?;
+        self.assets.set_title(id, title);
+        Ok(())
+    }
+
+    /// Points the asset to a different primary file, see `AssetStore::set_file`.
+    /// The asset keeps its id, title and tags. The old file stays in the file store.
+    pub fn replace_asset_file(&mut self, id: AssetId, file: FileId) -> Result<(), DataError> {
+        if !self.files.contains(file) {
+            return Err(DataError::FileNotFound(file));
+        }
+
+        if self.assets.set_file(id, file) {
+            Ok(())
+        } else {
+            Err(DataError::AssetNotFound(id))
+        }
+    }
+
+    /// Removes an asset, also from the collections it is in. The file it refers to is kept.
+    pub fn remove_asset(&mut self, id: AssetId) -> Result<(), DataError> {
+        self.assets
+            .remove(&id)
+            .ok_or(DataError::AssetNotFound(id))?;
+        self.collections.remove_from_all(id);
+        self.notify(StoreEvent::AssetRemoved(id));
+        Ok(())
+    }
+
+    pub fn asset_count(&self) -> usize {
+        self.assets.count()
+    }
+
+    pub fn get_asset(&self, id: AssetId) -> Option<&Asset> {
+        self.assets.get(id)
+    }
+
+    /// All the assets, in no particular order.
+    /// Archived assets are left out, unless `include_archived` is set.
+    pub fn asset_iter(&self, include_archived: bool) -> StoreIter<'_, AssetId, Asset> {
+        Box::new(
+            self.assets
+                .iter()
+                .filter(move |(_, asset)| include_archived || !asset.is_archived()),
+        )
+    }
+
+    /// Archives an asset, or brings it back, see `AssetStore::set_archived`.
+    /// Will return an error if the asset is not known.
+    pub fn set_asset_archived(&mut self, id: AssetId, archived: bool) -> Result<(), DataError> {
+        if self.assets.set_archived(id, archived) {
+            Ok(())
+        } else {
+            Err(DataError::AssetNotFound(id))
+        }
+    }
+
+    /// How many assets use the given file. Files that are used can't be removed.
+    pub fn file_reference_count(&self, id: FileId) -> usize {
+        self.assets.assets_using_file(id).len()
+    }
+
+    /// Finds the files that no asset refers to, like to clean up storage. The ids are sorted.
+    pub fn orphan_files(&self) -> Vec<FileId> {
+        let used = self.assets.used_files();
+        let mut orphans: Vec<FileId> = self
+            .files
+            .ids()
+            .filter(|id| !used.contains(id))
+            .copied()
+            .collect();
+
+        orphans.sort();
+        orphans
+    }
+
+    /// Finds the assets that use the given file. The ids are sorted.
+    pub fn find_assets_by_file(&self, file: FileId) -> Vec<AssetId> {
+        let mut found = self.assets.assets_using_file(file);
+        found.sort();
+        found
+    }
+
+    /// Counts and sizes across all the stores, for example for a dashboard.
+    pub fn summary(&self) -> Summary {
+        let mut summary = Summary {
+            file_count: self.files.count(),
+            asset_count: self.assets.count(),
+            collection_count: self.collections.count(),
+            image_count: 0,
+            total_size_bytes: 0,
+            by_extension: HashMap::new(),
+        };
+
+        for file in self.files.values() {
+            if file.extension().is_image() {
+                summary.image_count += 1;
+            }
+            summary.total_size_bytes += file.size_bytes();
+            *summary
+                .by_extension
+                .entry(file.extension().to_str().to_string())
+                .or_insert(0) += 1;
+        }
+        summary
+    }
+
+    /// How many items each store has, like "2 file(s), 1 asset(s), 0 collection(s)".
+    pub fn describe(&self) -> String {
+        StoreKind::ALL
+            .iter()
+            .map(|&kind| self.describe_store(kind))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Removes everything from every store, and the files from disk.
+    /// Ids that were handed out before will not be handed out again.
+    /// Will return an error if a file could not be removed, see `remove_file`.
+    pub fn clear_all(&mut self) -> Result<(), DataError> {
+        // The other way around, so that nothing refers to what is being cleared.
+        for &kind in StoreKind::ALL.iter().rev() {
+            self.clear_store(kind)?;
+        }
+        Ok(())
+    }
+
+    fn store_count(&self, kind: StoreKind) -> usize {
+        match kind {
+            StoreKind::Files => self.files.count(),
+            StoreKind::Assets => self.assets.count(),
+            StoreKind::Collections => self.collections.count(),
+        }
+    }
+
+    fn describe_store(&self, kind: StoreKind) -> String {
+        match kind {
+            StoreKind::Files => describe_count(&self.files),
+            StoreKind::Assets => describe_count(&self.assets),
+            StoreKind::Collections => describe_count(&self.collections),
+        }
+    }
+
+    /// Removes everything from one store. Assets are removed one by one, to notify about them,
+    /// and files are removed from disk as well.
+    fn clear_store(&mut self, kind: StoreKind) -> Result<(), DataError> {
+        match kind {
+            StoreKind::Files => self.clear_files(),
+            StoreKind::Assets => {
+                let ids: Vec<AssetId> = self.assets.ids().copied().collect();
+                for id in ids {
+                    self.remove_asset(id)?;
+                }
+                Ok(())
+            }
+            StoreKind::Collections => {
+                self.collections.clear();
+                Ok(())
+            }
+        }
+    }
+
+    /// Creates a new, empty collection to put assets in.
+    pub fn new_collection(&mut self, name: &str) -> Result<CollectionId, DataError> {
+        Ok(self.collections.new_collection(name)?)
+    }
+
+    /// Adds an asset to a collection. Adding an asset that is already in it changes nothing.
+    /// Will return an error if the collection or the asset is not known.
+    pub fn add_to_collection(
+        &mut self,
+        collection: CollectionId,
+        asset: AssetId,
+    ) -> Result<(), DataError> {
+        if !self.collections.contains(collection) {
+            return Err(DataError::CollectionNotFound(collection));
+        }
+        if !self.assets.contains(asset) {
+            return Err(DataError::AssetNotFound(asset));
+        }
+
+        self.collections.add_member(collection, asset);
+        Ok(())
+    }
+
+    /// Removes an asset from a collection, if it is in it.
+    /// Will return an error if the collection is not known.
+    pub fn remove_from_collection(
+        &mut self,
+        collection: CollectionId,
+        asset: AssetId,
+    ) -> Result<(), DataError> {
+        if !self.collections.contains(collection) {
+            return Err(DataError::CollectionNotFound(collection));
+        }
+
+        self.collections.remove_member(collection, asset);
+        Ok(())
+    }
+
+    pub fn get_collection(&self, id: CollectionId) -> Option<&Collection> {
+        self.collections.get(id)
+    }
+
+    /// The collections that the asset is in, see `CollectionStore::collections_with`.
+    pub fn collections_with_asset(&self, asset: AssetId) -> Vec<CollectionId> {
+        self.collections.collections_with(asset)
+    }
+}
+
+/// Sets up a `Data`, for when the defaults of `Data::new` are not what you need.
+pub struct DataBuilder {
+    save_dir: PathBuf,
+    files_dir: PathBuf,
+    #[cfg(feature = "image")]
+    thumbnails_dir: Option<PathBuf>,
+    create_dirs: bool,
+    #[cfg(feature = "image")]
+    verify_images: bool,
+    backend: Option<Box<dyn FileBackend>>,
+}
+
+impl DataBuilder {
+    /// - `save_dir`: The directory to save the data files.
+    /// - `files_dir`: The directory where the actual files will be stored.
+    pub fn new(save_dir: &Path, files_dir: &Path) -> DataBuilder {
+        DataBuilder {
+            save_dir: save_dir.to_path_buf(),
+            files_dir: files_dir.to_path_buf(),
+            #[cfg(feature = "image")]
+            thumbnails_dir: None,
+            create_dirs: true,
+            #[cfg(feature = "image")]
+            verify_images: false,
+            backend: None,
+        }
+    }
+
+    /// Where to cache thumbnails. Defaults to a directory in the save directory.
+    #[cfg(feature = "image")]
+    pub fn thumbnails_dir(mut self, thumbnails_dir: &Path) -> DataBuilder {
+        self.thumbnails_dir = Some(thumbnails_dir.to_path_buf());
+        self
+    }
+
+    /// Whether to create the save and files directories when building. Defaults to true.
+    /// When false, they are created when something is first written to them.
+    pub fn create_dirs(mut self, create_dirs: bool) -> DataBuilder {
+        self.create_dirs = create_dirs;
+        self
+    }
+
+    /// Whether to decode imported images, and reject the ones that turn out not to be
+    /// an image of the type their extension claims. Defaults to false.
+    #[cfg(feature = "image")]
+    pub fn verify_images(mut self, verify_images: bool) -> DataBuilder {
+        self.verify_images = verify_images;
+        self
+    }
+
+    /// Where to keep the contents of the files. Defaults to a `DiskBackend`.
+    /// The index and the thumbnails are not stored files, so they are always kept on disk,
+    /// in the save and thumbnails directories.
+    pub fn backend(mut self, backend: Box<dyn FileBackend>) -> DataBuilder {
+        self.backend = Some(backend);
+        self
+    }
+
+    pub fn build(self) -> Result<Data, DataError> {
+        let mut backend = match self.backend {
+            Some(backend) => backend,
+            None => disk_backend(),
+        };
+
+        if self.create_dirs {
+            std::fs::create_dir_all(&self.save_dir).map_err(DataError::io(&self.save_dir))?;
+            backend
+                .create_dir_all(&self.files_dir)
+                .map_err(DataError::io(&self.files_dir))?;
+        }
+
+        #[cfg(feature = "image")]
+        let thumbnails_dir = match self.thumbnails_dir {
+            Some(thumbnails_dir) => thumbnails_dir,
+            None => self.save_dir.join(THUMBNAILS_DIR_NAME),
+        };
+
+        Ok(Data {
+            #[cfg(feature = "image")]
+            thumbnails_dir,
+            backend,
+            save_dir: self.save_dir,
+            files_dir: self.files_dir,
+            files: FileStore::new(),
+            assets: AssetStore::new(),
+            collections: CollectionStore::new(),
+            on_change: None,
+            #[cfg(feature = "image")]
+            verify_images: self.verify_images,
+        })
+    }
+
+    /// Like `build`, but with the stores loaded from the index in the save directory,
+    /// see `Data::load`.
+    #[cfg(feature = "serde")]
+    pub fn load(self) -> Result<Data, DataError> {
+        let empty = self.build()?;
+
+        let index_path = empty.save_dir.join(INDEX_FILE_NAME);
+        if !index_path.exists() {
+            // Without the directory there is nothing to recover, like when it is not created yet.
+            // Recovering reads the directory itself, so that only works for files on disk.
+            if !empty.backend.is_on_disk() || !empty.files_dir.is_dir() {
+                return Ok(empty);
+            }
+            return Ok(Data {
+                files: FileStore::from_directory(&empty.files_dir)?,
+                ..empty
+            });
+        }
+
+        let index = std::fs::read_to_string(&index_path).map_err(DataError::io(&index_path))?;
+        let IndexVersion { version } = serde_json::from_str(&index)?;
+        if version > INDEX_VERSION {
+            return Err(DataError::UnsupportedIndexVersion {
+                found: version,
+                supported: INDEX_VERSION,
+            });
+        }
+        let loaded: Data = serde_json::from_str(&index)?;
+
+        // Only the stores come from the index, the rest is configuration.
+        Ok(Data {
+            files: loaded.files,
+            assets: loaded.assets,
+            collections: loaded.collections,
+            ..empty
+        })
+    }
+}
+
+fn disk_backend() -> Box<dyn FileBackend> {
+    Box::new(DiskBackend)
+}
+
+/// Checks a title before it reaches the stores, see `validate_title`,
+/// so that what is wrong with it ends up in the error.
+fn checked_title(title: &str) -> Result<(), DataError> {
+    match validate_title(title) {
+        Ok(_) => Ok(()),
+        Err(_) if title.trim().is_empty() => Err(DataError::EmptyTitle),
+        Err(_) => Err(DataError::InvalidTitle(title.to_string())),
+    }
+}
+
+/// Quotes a CSV field when it has to be, doubling any quotes in it.
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Replaces the index in `save_dir` in one go, so when writing fails halfway the old index
+/// is kept.
+#[cfg(feature = "serde")]
+fn write_index(save_dir: &Path, index: &[u8]) -> Result<(), DataError> {
+    let index_path = save_dir.join(INDEX_FILE_NAME);
+    let temp_path = save_dir.join(INDEX_TEMP_FILE_NAME);
+
+    if let Err(e) = write_synced(&temp_path, index) {
+        let _ = std::fs::remove_file(&temp_path);
+        return Err(DataError::io(&temp_path)(e));
+    }
+    std::fs::rename(&temp_path, &index_path).map_err(DataError::io(&index_path))
+}
+
+/// Writes the bytes to a new file, and makes sure they are on disk before returning.
+#[cfg(feature = "serde")]
+fn write_synced(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(path)?;
+    std::io::Write::write_all(&mut file, bytes)?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file_backend::InMemoryBackend;
+    #[cfg(feature = "rayon")]
+    use crate::stores::id_allocator::SequentialAllocator;
+    use anyhow::Result;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use tempfile;
+    use tempfile::TempDir;
+
+    const TEST_FILES_PATH: &str = "tests/files";
+
+    #[test]
+    fn check_data_initialization() {
+        // Setup a temporary directory for the test.
+        let (_, save_dir, file_dir) = setup_temp_directory();
+
+        // Directories should at the moment not be there.
+        assert!(!save_dir.exists());
+        assert!(!file_dir.exists());
+
+        // Initialize the data.
+        let data = Data::new(&save_dir, &file_dir);
+
+        // Now they should be there.
+        assert!(save_dir.exists());
+        assert!(file_dir.exists());
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn builder_with_custom_thumbnails_dir() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let thumbnails_dir = temp.path().join("previews");
+        let mut data = DataBuilder::new(&save_dir, &file_dir)
+            .thumbnails_dir(&thumbnails_dir)
+            .build()?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let thumbnail = data.generate_thumbnail(id, &ThumbnailOptions::default())?;
+
+        assert!(thumbnail.starts_with(&thumbnails_dir));
+        assert!(!save_dir.join(THUMBNAILS_DIR_NAME).exists());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn verified_imports_reject_fake_images() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = DataBuilder::new(&save_dir, &file_dir)
+            .verify_images(true)
+            .build()?;
+
+        let bogus = temp.path().join("bogus.png");
+        std::fs::write(&bogus, "This is not an image.")?;
+        let result = data.add_file_from_disk("Bogus", &bogus);
+        assert!(matches!(result, Err(DataError::NotAnImage(path)) if path == bogus));
+
+        // A png pretending to be a jpg is not accepted either.
+        let test_files = Path::new(TEST_FILES_PATH);
+        let disguised = temp.path().join("disguised.jpg");
+        std::fs::copy(test_files.join("swords/tall.png"), &disguised)?;
+        assert!(data.add_file_from_disk("Disguised", &disguised).is_err());
+        let bytes = std::fs::read(&disguised)?;
+        let result = data.add_file_from_bytes("Disguised", KnownExtension::Jpeg, &bytes);
+        assert!(
+            matches!(result, Err(DataError::InvalidImageContent(title)) if title == "Disguised")
+        );
+        let result = data.add_file_from_reader("Disguised", KnownExtension::Jpeg, &bytes[..]);
+        assert!(
+            matches!(result, Err(DataError::InvalidImageContent(title)) if title == "Disguised")
+        );
+        assert_eq!(data.file_count(), 0);
+
+        data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        data.add_file_from_bytes("Tall", KnownExtension::Png, &bytes)?;
+        assert_eq!(data.file_count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_file_from_reader() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let bytes = std::fs::read(Path::new(TEST_FILES_PATH).join("swords/tall.png"))?;
+        let id = data.add_file_from_reader(
+            "Tall",
+            KnownExtension::Png,
+            std::io::Cursor::new(bytes.clone()),
+        )?;
+
+        let file = data.get_file_info(id).unwrap();
+        assert_eq!(file.size_bytes(), 17797);
+        assert_eq!(
+            file.content_hash(),
+            Some(content_hash::hash_bytes(&bytes).as_str())
+        );
+        assert_eq!(std::fs::read(data.file_path(id).unwrap())?, bytes);
+
+        // The same contents again are deduplicated, without leaving a copy behind.
+        let again =
+            data.add_file_from_reader("Again", KnownExtension::Png, std::io::Cursor::new(bytes))?;
+        assert_eq!(again, id);
+        assert_eq!(data.file_count(), 1);
+        assert_eq!(std::fs::read_dir(&file_dir)?.count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_add_file_from_reader_leaves_nothing_behind() -> Result<()> {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("connection lost"))
+            }
+        }
+
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        assert!(matches!(
+            data.add_file_from_reader("Upload", KnownExtension::Png, Failing),
+            Err(DataError::Io { .. })
+        ));
+        assert_eq!(data.file_count(), 0);
+        assert_eq!(std::fs::read_dir(&file_dir)?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn files_can_be_kept_in_memory() -> Result<()> {
+        let save_dir = Path::new("/nonexistent/asset_keeper");
+        let mut data = DataBuilder::new(save_dir, &save_dir.join("files"))
+            .create_dirs(false)
+            .backend(Box::new(InMemoryBackend::new()))
+            .build()?;
+
+        let bytes = std::fs::read(Path::new(TEST_FILES_PATH).join("swords/tall.png"))?;
+        let id = data.add_file_from_bytes("Tall", KnownExtension::Png, &bytes)?;
+        assert_eq!(data.read_file_bytes(id)?, bytes);
+        assert_eq!(data.get_file_info(id).unwrap().size_bytes(), 17797);
+        #[cfg(feature = "image")]
+        assert_eq!(
+            data.get_file_info(id).unwrap().dimensions(),
+            Some((350, 600))
+        );
+        assert!(data.check_integrity().is_empty());
+
+        data.remove_file(id)?;
+        assert!(data.read_file_bytes(id).is_err());
+        assert!(!save_dir.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn in_memory_files_can_be_moved_and_reconciled() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = DataBuilder::new(&save_dir, &file_dir)
+            .backend(Box::new(InMemoryBackend::new()))
+            .build()?;
+
+        let id = data.add_file_from_bytes("Sword", KnownExtension::Gif, b"sword")?;
+        let new_dir = temp.path().join("moved");
+        data.move_files_dir(&new_dir)?;
+        assert_eq!(data.read_file_bytes(id)?, b"sword");
+
+        // Misnamed, like after a crash.
+        let stray = new_dir.join("renamed.gif");
+        data.backend.rename(&data.file_path(id).unwrap(), &stray)?;
+        let report = data.reconcile_disk(true)?;
+        assert_eq!(report.fixed, vec![(stray, id)]);
+        assert_eq!(data.read_file_bytes(id)?, b"sword");
+
+        assert!(!file_dir.exists());
+        assert!(!new_dir.exists());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn thumbnails_of_in_memory_files() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = DataBuilder::new(&save_dir, &file_dir)
+            .backend(Box::new(InMemoryBackend::new()))
+            .build()?;
+
+        let bytes = std::fs::read(Path::new(TEST_FILES_PATH).join("swords/tall.png"))?;
+        let id = data.add_file_from_bytes("Tall", KnownExtension::Png, &bytes)?;
+        let options = ThumbnailOptions {
+            max_dim: 120,
+            ..Default::default()
+        };
+
+        let thumbnail = data.generate_thumbnail(id, &options)?;
+        assert_eq!(images::dimensions(&thumbnail)?, (70, 120));
+        let modified = std::fs::metadata(&thumbnail)?.modified()?;
+        assert_eq!(data.generate_thumbnail(id, &options)?, thumbnail);
+        assert_eq!(std::fs::metadata(&thumbnail)?.modified()?, modified);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_import_into_memory() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = DataBuilder::new(&save_dir, &file_dir)
+            .backend(Box::new(InMemoryBackend::new()))
+            .build()?;
+
+        let swords = Path::new(TEST_FILES_PATH).join("swords");
+        let summary = data.import_directory_parallel(&swords, false)?;
+
+        assert_eq!(summary.imported.len(), 3);
+        assert!(summary.failed.is_empty());
+        assert_eq!(
+            data.read_file_bytes(summary.imported[0])?,
+            std::fs::read(swords.join("square_crossed.png"))?
+        );
+        assert!(!file_dir.exists());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn add_file_from_disk_async_into_memory() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = DataBuilder::new(&save_dir, &file_dir)
+            .backend(Box::new(InMemoryBackend::new()))
+            .build()?;
+
+        let source = Path::new(TEST_FILES_PATH).join("swords/tall.png");
+        let id = data.add_file_from_disk_async("Tall", &source).await?;
+
+        assert_eq!(data.read_file_bytes(id)?, std::fs::read(&source)?);
+        assert_eq!(data.get_file_info(id).unwrap().size_bytes(), 17797);
+        assert!(!file_dir.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn errors_show_plain_ids() {
+        use crate::stores::traits::StoreId;
+
+        assert_eq!(
+            DataError::FileNotFound(FileId::from_u32(3)).to_string(),
+            "There is no file with id 3"
+        );
+        let in_use = DataError::FileInUse {
+            file: FileId::from_u32(3),
+            assets: vec![AssetId::from_u32(1), AssetId::from_u32(2)],
+        };
+        assert_eq!(in_use.to_string(), "File 3 is still used by assets 1, 2");
+    }
+
+    #[test]
+    fn builder_without_creating_dirs() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = DataBuilder::new(&save_dir, &file_dir)
+            .create_dirs(false)
+            .build()?;
+
+        assert!(!save_dir.exists());
+        assert!(!file_dir.exists());
+
+        // The first file creates the directories.
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        assert!(data.file_path(id).unwrap().is_file());
+
+        Ok(())
+    }
+
+    #[test]
+    fn on_change_is_called_in_order() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let events_clone = Rc::clone(&events);
+        data.set_on_change(Box::new(move |event| events_clone.borrow_mut().push(event)));
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let file = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let asset = data.new_asset("Tall sword", &[file])?;
+        // Already stored, so nothing changes.
+        data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        data.remove_asset(asset)?;
+        data.remove_file(file)?;
+
+        assert_eq!(
+            *events.borrow(),
+            vec![
+                StoreEvent::FileAdded(file),
+                StoreEvent::AssetAdded(asset),
+                StoreEvent::AssetRemoved(asset),
+                StoreEvent::FileRemoved(file),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_files_skips_blocked_ones() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let first = data.add_file_from_bytes("First", KnownExtension::Gif, b"first")?;
+        let used = data.add_file_from_bytes("Used", KnownExtension::Gif, b"used")?;
+        let second = data.add_file_from_bytes("Second", KnownExtension::Gif, b"second")?;
+        let asset = data.new_asset("Asset", &[used])?;
+        let first_path = data.file_path(first).unwrap();
+        let unknown: FileId = "99".parse()?;
+
+        let report = data.remove_files(&[first, used, unknown, second]);
+        assert_eq!(report.removed, vec![first, second]);
+        assert_eq!(report.failed.len(), 2);
+        assert!(matches!(
+            &report.failed[0],
+            (file, DataError::FileInUse { assets, .. }) if *file == used && assets == &vec![asset]
+        ));
+        assert!(matches!(
+            report.failed[1],
+            (file, DataError::FileNotFound(_)) if file == unknown
+        ));
+
+        assert_eq!(data.file_count(), 1);
+        assert!(data.get_file_info(used).is_some());
+        assert!(!first_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn retain_files_removes_from_disk() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let kept = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let dropped = data.add_file_from_disk("Old wide", &test_files.join("swords/wide.png"))?;
+        let used = data.add_file_from_bytes("Old used", KnownExtension::Gif, b"used")?;
+        let asset = data.new_asset("Asset", &[used])?;
+        let dropped_path = data.file_path(dropped).unwrap();
+
+        // One of the files is still in use, so nothing happens.
+        let in_use = data.retain_files(|_, file| !file.title().starts_with("Old"));
+        assert!(matches!(in_use, Err(DataError::FileInUse { file, .. }) if file == used));
+        assert_eq!(data.file_count(), 3);
+
+        data.remove_asset(asset)?;
+        data.retain_files(|_, file| !file.title().starts_with("Old"))?;
+        assert_eq!(data.file_count(), 1);
+        assert!(data.get_file_info(kept).is_some());
+        assert!(!dropped_path.exists());
+        assert!(data.file_path(kept).unwrap().exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn count_references_to_files() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let unused = data.add_file_from_disk("Unused", &test_files.join("swords/tall.png"))?;
+        let once = data.add_file_from_disk("Once", &test_files.join("swords/wide.png"))?;
+        let shared = data.add_file_from_bytes("Shared", KnownExtension::Gif, b"shared")?;
+        data.new_asset("First", &[once, shared])?;
+        let second = data.new_asset("Second", &[shared])?;
+        data.new_asset("Third", &[shared])?;
+
+        assert_eq!(data.file_reference_count(unused), 0);
+        assert_eq!(data.file_reference_count(once), 1);
+        assert_eq!(data.file_reference_count(shared), 3);
+
+        data.remove_asset(second)?;
+        assert_eq!(data.file_reference_count(shared), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn list_assets_and_find_them_by_file() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let shared = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let own = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+        let unused = data.add_file_from_bytes("Unused", KnownExtension::Gif, b"unused")?;
+        let first = data.new_asset("First", &[shared])?;
+        let second = data.new_asset("Second", &[own, shared])?;
+
+        let mut titles: Vec<(AssetId, &str)> = data
+            .asset_iter(false)
+            .map(|(id, asset)| (*id, asset.title()))
+            .collect();
+        titles.sort();
+        assert_eq!(titles, vec![(first, "First"), (second, "Second")]);
+
+        assert_eq!(data.find_assets_by_file(shared), vec![first, second]);
+        assert_eq!(data.find_assets_by_file(own), vec![second]);
+        assert!(data.find_assets_by_file(unused).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn orphan_files_are_not_used_by_any_asset() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let used = data.add_file_from_bytes("Used", KnownExtension::Gif, b"used")?;
+        let first = data.add_file_from_bytes("First", KnownExtension::Gif, b"first")?;
+        let second = data.add_file_from_bytes("Second", KnownExtension::Gif, b"second")?;
+        data.new_asset("Asset", &[used])?;
+        assert_eq!(data.orphan_files(), vec![first, second]);
+
+        data.new_asset("Other", &[second])?;
+        assert_eq!(data.orphan_files(), vec![first]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn archived_assets_are_hidden_by_default() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let active = data.new_asset("Active", &[])?;
+        let archived = data.new_asset("Archived", &[])?;
+        data.set_asset_archived(archived, true)?;
+
+        let ids = |include_archived| {
+            let mut ids: Vec<AssetId> = data
+                .asset_iter(include_archived)
+                .map(|(id, _)| *id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(false), vec![active]);
+        assert_eq!(ids(true), vec![active, archived]);
+        assert!(matches!(
+            data.set_asset_archived("99".parse()?, true),
+            Err(DataError::AssetNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_assets() -> Result<()> {
+        // Setup a temporary directory for the test.
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        // Initialize the data.
+        let mut data = Data::new(&save_dir, &file_dir).unwrap();
+
+        let test_files = Path::new(TEST_FILES_PATH);
+
+        let title = "Testing title";
+
+        let id = data.add_file_from_disk(title, &test_files.join(Path::new("swords/tall.png")))?;
+
+        // Check if it has been created properly.
+        assert_eq!(data.file_count(), 1);
+        let asset = data.get_file_info(id).unwrap();
+        assert_eq!(asset.title(), title);
+
+        // TODO: Check if the file can be retrieved as well.
+
+        Ok(())
+    }
+
+    #[test]
+    fn added_files_are_copied_to_files_dir() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id = data.add_file_from_disk("Sword", &test_files.join("swords/tall.png"))?;
+        let file_name = data.get_file_info(id).unwrap().file_name();
+
+        // The copy should end up in the files directory, not in the save directory.
+        assert!(file_dir.join(&file_name).is_file());
+        assert!(!save_dir.join(&file_name).exists());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn transparent_files_get_tagged() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let opaque = data.add_file_from_disk("Opaque", &test_files.join("swords/tall.png"))?;
+        let transparent = data.add_file_from_disk(
+            "Transparent",
+            &test_files.join("swords_transparent/tall_t.png"),
+        )?;
+
+        let opaque_tags = data.get_file_info(opaque).unwrap().system_tags();
+        assert!(!opaque_tags.contains(&SystemTag::Transparent));
+        let transparent_tags = data.get_file_info(transparent).unwrap().system_tags();
+        assert!(transparent_tags.contains(&SystemTag::Transparent));
+        assert_eq!(
+            data.files_with_system_tag(SystemTag::Transparent),
+            vec![transparent]
+        );
+        assert!(!data.get_file_info(opaque).unwrap().is_transparent());
+        assert!(data.get_file_info(transparent).unwrap().is_transparent());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn undecodable_files_count_as_opaque() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let id = data.add_file_from_bytes("Broken", KnownExtension::Png, b"not a png")?;
+        assert!(!data.get_file_info(id).unwrap().is_transparent());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn image_dimensions_are_recorded() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let tall = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let wide = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+
+        assert_eq!(
+            data.get_file_info(tall).unwrap().dimensions(),
+            Some((350, 600))
+        );
+        assert_eq!(
+            data.get_file_info(wide).unwrap().dimensions(),
+            Some((600, 350))
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn thumbnails_are_generated_and_cached() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+
+        let options = ThumbnailOptions {
+            max_dim: 100,
+            ..Default::default()
+        };
+        let thumbnail = data.generate_thumbnail(id, &options)?;
+        assert!(thumbnail.starts_with(save_dir.join(THUMBNAILS_DIR_NAME)));
+        let (width, height) = images::dimensions(&thumbnail)?;
+        assert!(width <= 100 && height <= 100);
+        assert_eq!(width, 100);
+
+        // The second time, the cached thumbnail should be used.
+        let modified = std::fs::metadata(&thumbnail)?.modified()?;
+        assert_eq!(data.generate_thumbnail(id, &options)?, thumbnail);
+        assert_eq!(std::fs::metadata(&thumbnail)?.modified()?, modified);
+
+        // A different size should regenerate it.
+        let options = ThumbnailOptions {
+            max_dim: 50,
+            ..Default::default()
+        };
+        data.generate_thumbnail(id, &options)?;
+        assert_eq!(images::dimensions(&thumbnail)?, (50, 29));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn convert_file_does_not_overwrite_stale_files() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let source = Path::new(TEST_FILES_PATH).join("swords/tall.png");
+        let id = data.add_file_from_disk("Tall", &source)?;
+        let png_path = data.file_path(id).unwrap();
+        // Left behind by an earlier conversion that crashed.
+        let stale = file_dir.join(format!("{}.jpg", id));
+        std::fs::write(&stale, "stale")?;
+
+        let result = data.convert_file(id, KnownExtension::Jpeg);
+
+        assert!(matches!(result, Err(DataError::StaleFile(path)) if path == stale));
+        assert_eq!(std::fs::read(&stale)?, b"stale");
+        assert_eq!(data.file_path(id).unwrap(), png_path);
+        assert_eq!(std::fs::read(&png_path)?, std::fs::read(&source)?);
+        assert!(data.verify_file(id)?);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn convert_png_to_jpeg() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let source = test_files.join("swords_transparent/tall_t.png");
+        let id = data.add_file_from_disk("Tall", &source)?;
+        let old_path = data.file_path(id).unwrap();
+        assert!(data.get_file_info(id).unwrap().is_transparent());
+
+        data.convert_file(id, KnownExtension::Jpeg)?;
+
+        let file = data.get_file_info(id).unwrap();
+        assert_eq!(file.extension(), &KnownExtension::Jpeg);
+        assert_eq!(file.file_name(), PathBuf::from(format!("{}.jpg", id)));
+        assert!(!file.is_transparent());
+        assert_eq!(file.dimensions(), Some(images::dimensions(&source)?));
+        assert!(!old_path.exists());
+        let new_path = data.file_path(id).unwrap();
+        assert!(images::is_valid_file(&new_path, &KnownExtension::Jpeg));
+        assert!(data.verify_file(id)?);
+
+        assert!(matches!(
+            data.convert_file(id, KnownExtension::Svg),
+            Err(DataError::NotARasterFormat("svg"))
+        ));
+        assert_eq!(
+            data.get_file_info(id).unwrap().extension(),
+            &KnownExtension::Jpeg
+        );
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn jpeg_thumbnails() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+
+        let options = ThumbnailOptions {
+            format: KnownExtension::Jpeg,
+            max_dim: 100,
+            jpeg_quality: 60,
+        };
+        let thumbnail = data.generate_thumbnail(id, &options)?;
+        assert_eq!(thumbnail.extension().unwrap(), "jpg");
+        assert!(images::is_valid_file(&thumbnail, &KnownExtension::Jpeg));
+        assert_eq!(images::dimensions(&thumbnail)?.0, 100);
+
+        for quality in &[0, 101] {
+            let options = ThumbnailOptions {
+                format: KnownExtension::Jpeg,
+                max_dim: 100,
+                jpeg_quality: *quality,
+            };
+            assert!(matches!(
+                data.generate_thumbnail(id, &options),
+                Err(DataError::InvalidJpegQuality(q)) if q == *quality
+            ));
+        }
+
+        let options = ThumbnailOptions {
+            format: KnownExtension::Svg,
+            ..Default::default()
+        };
+        assert!(matches!(
+            data.generate_thumbnail(id, &options),
+            Err(DataError::NotARasterFormat("svg"))
+        ));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn undecodable_files_are_still_added() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let bogus = temp.path().join("bogus.png");
+        std::fs::write(&bogus, "This is not an image.")?;
+        let id = data.add_file_from_disk("Bogus", &bogus)?;
+
+        assert!(data.get_file_info(id).unwrap().system_tags().is_empty());
+        assert!(data.get_file_info(id).unwrap().dimensions().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn new_asset_refers_to_existing_file() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let file = data.add_file_from_disk("Sword", &test_files.join("swords/tall.png"))?;
+        let id = data.new_asset("Tall sword", &[file])?;
+
+        assert_eq!(data.asset_count(), 1);
+        let asset = data.get_asset(id).unwrap();
+        assert_eq!(asset.title(), "Tall sword");
+        assert_eq!(asset.files(), &[file]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn new_asset_with_unknown_file_is_rejected() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let file = data.add_file_from_disk("Sword", &test_files.join("swords/tall.png"))?;
+        data.remove_file(file)?;
+
+        assert!(data.new_asset("Tall sword", &[file]).is_err());
+        assert_eq!(data.asset_count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn other_image_extensions_can_be_added() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        for name in &["image.gif", "image.bmp", "image.webp"] {
+            let path = temp.path().join(name);
+            std::fs::write(&path, name)?;
+
+            let id = data.add_file_from_disk(name, &path)?;
+            let stored = data.file_path(id).unwrap();
+            assert_eq!(stored.extension(), path.extension());
+        }
+        assert_eq!(data.file_count(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tiff_and_ico_files_can_be_added() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        for (name, stored_as) in &[
+            ("scan.tif", "tiff"),
+            ("print.tiff", "tiff"),
+            ("icon.ico", "ico"),
+        ] {
+            let path = temp.path().join(name);
+            std::fs::write(&path, name)?;
+
+            let id = data.add_file_from_disk(name, &path)?;
+            let stored = data.file_path(id).unwrap();
+            assert_eq!(stored.extension().unwrap(), *stored_as);
+        }
+        assert_eq!(data.file_count(), 3);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn tiff_images_are_decoded() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = DataBuilder::new(&save_dir, &file_dir)
+            .verify_images(true)
+            .build()?;
+
+        let png = std::fs::read(Path::new(TEST_FILES_PATH).join("swords_transparent/tall_t.png"))?;
+        let tiff = images::convert_bytes(&png, &KnownExtension::Tiff, 100)?;
+        let id = data.add_file_from_bytes("Scan", KnownExtension::Tiff, &tiff)?;
+
+        let file = data.get_file_info(id).unwrap();
+        assert_eq!(file.dimensions(), Some((350, 600)));
+        assert!(file.is_transparent());
+        assert_eq!(data.summary().image_count, 1);
+
+        let options = ThumbnailOptions {
+            format: KnownExtension::Tiff,
+            ..Default::default()
+        };
+        let thumbnail = data.generate_thumbnail(id, &options)?;
+        assert!(images::is_valid_file(&thumbnail, &KnownExtension::Tiff));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "phash")]
+    #[test]
+    fn find_similar_images() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let tall = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let crossed =
+            data.add_file_from_disk("Crossed", &test_files.join("swords/square_crossed.png"))?;
+
+        let mut bytes = Vec::new();
+        image::open(test_files.join("swords/tall.png"))?
+            .resize(300, 300, image::imageops::FilterType::Triangle)
+            .write_to(
+                &mut std::io::Cursor::new(&mut bytes),
+                image::ImageFormat::Png,
+            )?;
+        let smaller = data.add_file_from_bytes("Smaller", KnownExtension::Png, &bytes)?;
+
+        assert!(data
+            .get_file_info(tall)
+            .unwrap()
+            .perceptual_hash()
+            .is_some());
+        assert_eq!(data.find_similar(tall, 5), vec![smaller]);
+        assert_eq!(data.find_similar(smaller, 5), vec![tall]);
+        assert!(data.find_similar(crossed, 5).is_empty());
+        assert_eq!(data.find_similar(tall, 64), vec![crossed, smaller]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn file_sizes_are_recorded() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let tall = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let bytes = std::fs::read(test_files.join("swords/wide.png"))?;
+        let wide = data.add_file_from_bytes("Wide", KnownExtension::Png, &bytes)?;
+
+        assert_eq!(data.get_file_info(tall).unwrap().size_bytes(), 17797);
+        assert_eq!(data.get_file_info(wide).unwrap().size_bytes(), 55360);
+        assert_eq!(data.total_size_bytes(), 17797 + 55360);
+
+        Ok(())
+    }
+
+    #[test]
+    fn move_files_dir_moves_everything() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let tall = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let wide = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+
+        let new_dir = temp.path().join("bigger_disk");
+        data.move_files_dir(&new_dir)?;
+
+        assert!(data.file_path(tall).unwrap().starts_with(&new_dir));
+        assert_eq!(
+            data.read_file_bytes(tall)?,
+            std::fs::read(test_files.join("swords/tall.png"))?
+        );
+        assert_eq!(
+            data.read_file_bytes(wide)?,
+            std::fs::read(test_files.join("swords/wide.png"))?
+        );
+        assert!(!file_dir.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_move_files_dir_moves_files_back() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let tall = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let wide = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+        // One of the files has gone missing, so it can't be moved.
+        std::fs::remove_file(data.file_path(wide).unwrap())?;
+
+        let new_dir = temp.path().join("bigger_disk");
+        assert!(data.move_files_dir(&new_dir).is_err());
+
+        assert!(data.file_path(tall).unwrap().starts_with(&file_dir));
+        assert!(data.file_path(tall).unwrap().exists());
+        assert_eq!(std::fs::read_dir(&new_dir)?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn file_path_points_to_stored_file() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id = data.add_file_from_disk("Sword", &test_files.join("swords/tall.png"))?;
+
+        let path = data.file_path(id).unwrap();
+        assert!(path.is_file());
+        assert!(path.starts_with(&file_dir));
+        assert!(path.ends_with(data.get_file_info(id).unwrap().file_name()));
+        assert_eq!(path.extension().unwrap(), "png");
+
+        data.remove_file(id)?;
+        assert!(data.file_path(id).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn file_with_path_matches_file_and_path() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let id = data.add_file_from_bytes("Sword", KnownExtension::Png, b"sword")?;
+
+        let (file, path) = data.file_with_path(id).unwrap();
+        assert_eq!(file.title(), "Sword");
+        assert_eq!(path, data.file_path(id).unwrap());
+        assert_eq!(path, file_dir.join(file.file_name()));
+
+        let unknown: FileId = "99".parse()?;
+        assert!(data.file_with_path(unknown).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_file_bytes_returns_original_contents() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let source = Path::new(TEST_FILES_PATH).join("swords/tall.png");
+        let id = data.add_file_from_disk("Sword", &source)?;
+
+        assert_eq!(data.read_file_bytes(id)?, std::fs::read(&source)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_file_bytes_distinguishes_unknown_and_missing() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let source = Path::new(TEST_FILES_PATH).join("swords/tall.png");
+        let id = data.add_file_from_disk("Sword", &source)?;
+        std::fs::remove_file(data.file_path(id).unwrap())?;
+
+        let missing = data.read_file_bytes(id).unwrap_err();
+        assert!(matches!(missing, DataError::Io { .. }));
+
+        data.remove_file(id)?;
+        let unknown = data.read_file_bytes(id).unwrap_err();
+        assert!(matches!(unknown, DataError::FileNotFound(unknown_id) if unknown_id == id));
+
+        Ok(())
+    }
+
+    #[test]
+    fn import_directory_recursively() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let summary = data.import_directory(Path::new(TEST_FILES_PATH), true)?;
+
+        // Only the pngs, not the sources or the config file.
+        assert_eq!(summary.imported.len(), 6);
+        assert!(summary.failed.is_empty());
+        assert_eq!(data.file_count(), 6);
+        assert!(data.file_iter().any(|(_, file)| file.title() == "tall_t"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn import_directory_non_recursively() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        assert!(data
+            .import_directory(test_files, false)?
+            .imported
+            .is_empty());
+
+        let summary = data.import_directory(&test_files.join("swords"), false)?;
+        assert_eq!(summary.imported.len(), 3);
+        assert_eq!(data.file_count(), 3);
+
+        Ok(())
+    }
+
+    /// Keeps the files in memory, but can't create the files directory.
+    struct NoDirsBackend(InMemoryBackend);
+
+    impl FileBackend for NoDirsBackend {
+        fn create_dir_all(&mut self, dir: &Path) -> std::io::Result<()> {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("Not allowed to create \"{}\"", dir.display()),
+            ))
+        }
+
+        fn copy_from_disk(&mut self, source: &Path, dest: &Path) -> std::io::Result<u64> {
+            self.0.copy_from_disk(source, dest)
+        }
+
+        fn write(&mut self, dest: &Path, bytes: &[u8]) -> std::io::Result<()> {
+            self.0.write(dest, bytes)
+        }
+
+        fn write_from_reader(
+            &mut self,
+            dest: &Path,
+            reader: &mut dyn Read,
+        ) -> std::io::Result<u64> {
+            self.0.write_from_reader(dest, reader)
+        }
+
+        fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+            self.0.read(path)
+        }
+
+        fn remove(&mut self, path: &Path) -> std::io::Result<()> {
+            self.0.remove(path)
+        }
+
+        fn is_file(&self, path: &Path) -> bool {
+            self.0.is_file(path)
+        }
+
+        fn content_hash(&self, path: &Path) -> std::io::Result<String> {
+            self.0.content_hash(path)
+        }
+
+        fn rename(&mut self, from: &Path, to: &Path) -> std::io::Result<()> {
+            self.0.rename(from, to)
+        }
+
+        fn remove_dir(&mut self, dir: &Path) -> std::io::Result<()> {
+            self.0.remove_dir(dir)
+        }
+
+        fn list(&self, dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+            self.0.list(dir)
+        }
+
+        fn modified(&self, path: &Path) -> std::io::Result<std::time::SystemTime> {
+            self.0.modified(path)
+        }
+    }
+
+    #[test]
+    fn failing_to_create_the_files_dir_leaves_no_entries() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = DataBuilder::new(&save_dir, &file_dir)
+            .create_dirs(false)
+            .backend(Box::new(NoDirsBackend(InMemoryBackend::new())))
+            .build()?;
+        let swords = Path::new(TEST_FILES_PATH).join("swords");
+
+        let summary = data.import_directory(&swords, false)?;
+        assert_eq!(summary.failed.len(), 3);
+        assert_eq!(data.files.count(), 0);
+
+        #[cfg(feature = "rayon")]
+        {
+            let result = data.import_directory_parallel(&swords, false);
+            assert!(matches!(result, Err(DataError::Io { .. })));
+            assert_eq!(data.files.count(), 0);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_import_collects_running_out_of_ids() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+        // Only room for one more file.
+        data.files
+            .set_allocator(Box::new(SequentialAllocator::starting_at(u32::MAX - 1)));
+
+        let swords = Path::new(TEST_FILES_PATH).join("swords");
+        let summary = data.import_directory_parallel(&swords, false)?;
+
+        assert_eq!(summary.imported.len(), 1);
+        assert_eq!(summary.failed.len(), 2);
+        assert!(summary
+            .failed
+            .iter()
+            .all(|(_, e)| matches!(e, DataError::Store(_))));
+        assert_eq!(data.file_count(), 1);
+        assert!(data.file_path(summary.imported[0]).unwrap().exists());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn parallel_import_matches_sequential_import() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut sequential = Data::new(&save_dir, &file_dir)?;
+        let parallel_dir = temp.path().join("parallel");
+        let mut parallel = Data::new(&parallel_dir, &parallel_dir.join("files"))?;
+
+        // With a duplicate, to check that it is only stored once.
+        let import_dir = temp.path().join("import");
+        std::fs::create_dir(&import_dir)?;
+        let test_files = Path::new(TEST_FILES_PATH);
+        std::fs::copy(
+            test_files.join("swords/tall.png"),
+            import_dir.join("copy.png"),
+        )?;
+        for dir in &["swords", "swords_transparent"] {
+            std::fs::create_dir(import_dir.join(dir))?;
+            for entry in std::fs::read_dir(test_files.join(dir))? {
+                let path = entry?.path();
+                std::fs::copy(&path, import_dir.join(dir).join(path.file_name().unwrap()))?;
+            }
+        }
+
+        let expected = sequential.import_directory(&import_dir, true)?;
+        let summary = parallel.import_directory_parallel(&import_dir, true)?;
+
+        assert_eq!(summary.imported, expected.imported);
+        assert_eq!(summary.imported.len(), 7);
+        assert!(summary.failed.is_empty());
+        assert_eq!(parallel.file_count(), 6);
+        for (id, file) in sequential.file_iter() {
+            let parallel_file = parallel.get_file_info(*id).unwrap();
+            assert_eq!(parallel_file.title(), file.title());
+            assert_eq!(parallel_file.content_hash(), file.content_hash());
+            assert_eq!(
+                parallel.read_file_bytes(*id)?,
+                sequential.read_file_bytes(*id)?
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn preview_directory_matches_import() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let import_dir = temp.path().join("import");
+        std::fs::create_dir(&import_dir)?;
+        let test_files = Path::new(TEST_FILES_PATH).join("swords");
+        for name in &["tall.png", "wide.png", "not_an_asset.cfg"] {
+            std::fs::copy(test_files.join(name), import_dir.join(name))?;
+        }
+        std::fs::copy(
+            test_files.join("tall.png"),
+            import_dir.join("tall_copy.png"),
+        )?;
+        let stored = data.add_file_from_disk("Wide", &test_files.join("wide.png"))?;
+
+        let preview = data.preview_directory(&import_dir, false)?;
+        assert_eq!(preview.new, vec![import_dir.join("tall.png")]);
+        assert_eq!(preview.skipped.len(), 3);
+        assert!(
+            matches!(preview.skipped[0], (ref path, SkipReason::UnknownExtension)
+            if path == &import_dir.join("not_an_asset.cfg"))
+        );
+        assert!(
+            matches!(preview.skipped[1], (_, SkipReason::SameAs(ref earlier))
+            if earlier == &import_dir.join("tall.png"))
+        );
+        assert!(matches!(preview.skipped[2], (_, SkipReason::AlreadyStored(id)) if id == stored));
+
+        // Nothing happened yet.
+        assert_eq!(data.file_count(), 1);
+        assert_eq!(std::fs::read_dir(&file_dir)?.count(), 1);
+
+        data.import_directory(&import_dir, false)?;
+        assert_eq!(data.file_count(), 1 + preview.new.len());
+        assert_eq!(std::fs::read_dir(&file_dir)?.count(), 1 + preview.new.len());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn import_directory_collects_failures() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let import_dir = temp.path().join("import");
+        std::fs::create_dir(&import_dir)?;
+        std::fs::copy(
+            Path::new(TEST_FILES_PATH).join("swords/tall.png"),
+            import_dir.join("tall.png"),
+        )?;
+        // Looks like an image, but there is nothing to read.
+        std::os::unix::fs::symlink(temp.path().join("nothing"), import_dir.join("broken.png"))?;
+
+        let preview = data.preview_directory(&import_dir, false)?;
+        assert!(matches!(
+            &preview.skipped[..],
+            [(_, SkipReason::Failed(DataError::Io { .. }))]
+        ));
+
+        let summary = data.import_directory(&import_dir, false)?;
+
+        assert_eq!(summary.imported.len(), 1);
+        assert_eq!(summary.failed.len(), 1);
+        assert_eq!(summary.failed[0].0, import_dir.join("broken.png"));
+        assert_eq!(data.file_count(), 1);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn import_directory_collects_undecodable_images() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let import_dir = temp.path().join("import");
+        std::fs::create_dir(&import_dir)?;
+        std::fs::copy(
+            Path::new(TEST_FILES_PATH).join("swords/tall.png"),
+            import_dir.join("tall.png"),
+        )?;
+        std::fs::write(import_dir.join("broken.png"), b"not a png")?;
+
+        let summary = data.import_directory(&import_dir, false)?;
+
+        // Not being able to decode it does not stop the file from being imported.
+        assert_eq!(summary.imported.len(), 2);
+        assert!(summary.failed.is_empty());
+        let broken = summary.imported[0];
+        assert!(matches!(
+            &summary.undecoded[..],
+            [(id, DataError::Image { .. })] if *id == broken
+        ));
+        assert_eq!(data.get_file_info(broken).unwrap().dimensions(), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn rename_file_keeps_file_on_disk() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let file_name = data.get_file_info(id).unwrap().file_name();
+
+        data.rename_file(id, "Tall sword")?;
+        let file = data.get_file_info(id).unwrap();
+        assert_eq!(file.title(), "Tall sword");
+        assert_eq!(file.file_name(), file_name);
+        assert!(file_dir.join(file_name).exists());
+
+        assert!(matches!(
+            data.rename_file(id, " "),
+            Err(DataError::EmptyTitle)
+        ));
+        assert!(matches!(
+            data.rename_file(id, "Tall\nsword"),
+            Err(DataError::InvalidTitle(_))
+        ));
+        assert_eq!(data.get_file_info(id).unwrap().title(), "Tall sword");
+        let unknown: FileId = "99".parse()?;
+        assert!(matches!(
+            data.rename_file(unknown, "Other"),
+            Err(DataError::FileNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn search_files_by_title() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let tall = data.add_file_from_disk("Tall sword", &test_files.join("swords/tall.png"))?;
+        let wide = data.add_file_from_disk("Wide sword", &test_files.join("swords/wide.png"))?;
+
+        assert_eq!(data.search_files("sword"), vec![tall, wide]);
+        assert_eq!(data.search_files("wide"), vec![wide]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn list_files_sorted() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let banana = data.add_file_from_bytes("banana", KnownExtension::Gif, b"banana")?;
+        let apple = data.add_file_from_bytes("Apple", KnownExtension::Gif, b"apple")?;
+        let cherry = data.add_file_from_bytes("cherry", KnownExtension::Gif, b"cherry!")?;
+        let other_apple = data.add_file_from_bytes("apple", KnownExtension::Gif, b"apple 2")?;
+
+        assert_eq!(
+            data.list_files_sorted(SortKey::TitleAsc),
+            vec![apple, other_apple, banana, cherry]
+        );
+        assert_eq!(
+            data.list_files_sorted(SortKey::TitleDesc),
+            vec![cherry, banana, apple, other_apple]
+        );
+        assert_eq!(
+            data.list_files_sorted(SortKey::IdAsc),
+            vec![banana, apple, cherry, other_apple]
+        );
+        assert_eq!(
+            data.list_files_sorted(SortKey::SizeDesc),
+            vec![cherry, other_apple, banana, apple]
+        );
+        assert_eq!(data.list_files_sorted(SortKey::CreatedAtAsc).len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn count_files_by_extension() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        data.import_directory(&Path::new(TEST_FILES_PATH).join("swords"), false)?;
+        data.add_file_from_bytes("First", KnownExtension::Jpeg, b"first")?;
+        data.add_file_from_bytes("Second", KnownExtension::Jpeg, b"second")?;
+        data.add_file_from_bytes("Animated", KnownExtension::Gif, b"animated")?;
+
+        let counts = data.count_by_extension();
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts["png"], 3);
+        assert_eq!(counts["jpg"], 2);
+        assert_eq!(counts["gif"], 1);
+        assert!(!counts.contains_key("bmp"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn iterate_files_with_an_extension() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let first = data.add_file_from_bytes("First", KnownExtension::Jpeg, b"first")?;
+        let second = data.add_file_from_bytes("Second", KnownExtension::Jpeg, b"second")?;
+        data.add_file_from_bytes("Animated", KnownExtension::Gif, b"animated")?;
+
+        let mut jpegs: Vec<FileId> = data
+            .files_with_extension(&KnownExtension::Jpeg)
+            .map(|(id, file)| {
+                assert_eq!(file.extension(), &KnownExtension::Jpeg);
+                *id
+            })
+            .collect();
+        jpegs.sort();
+        assert_eq!(jpegs, vec![first, second]);
+        assert_eq!(data.files_with_extension(&KnownExtension::Png).count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_file_from_bytes_writes_to_files_dir() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let bytes = std::fs::read(Path::new(TEST_FILES_PATH).join("swords/tall.png"))?;
+        let id = data.add_file_from_bytes("Generated", KnownExtension::Png, &bytes)?;
+
+        let file = data.get_file_info(id).unwrap();
+        assert_eq!(file.title(), "Generated");
+        assert_eq!(std::fs::read(file_dir.join(file.file_name()))?, bytes);
+
+        Ok(())
+    }
+
+    #[test]
+    fn original_path_is_only_recorded_for_files_from_disk() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let source = Path::new(TEST_FILES_PATH).join("swords/tall.png");
+        let from_disk = data.add_file_from_disk("Tall", &source)?;
+        let from_bytes = data.add_file_from_bytes("Generated", KnownExtension::Png, b"bytes")?;
+
+        assert_eq!(
+            data.get_file_info(from_disk).unwrap().original_path(),
+            Some(source.as_path())
+        );
+        assert_eq!(
+            data.get_file_info(from_bytes).unwrap().original_path(),
+            None
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_add_file_from_bytes_leaves_no_entry() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        // Something is in the way of where the file should go.
+        std::fs::create_dir(file_dir.join("0.png"))?;
+        let result = data.add_file_from_bytes("Generated", KnownExtension::Png, b"bytes");
+
+        assert!(result.is_err());
+        assert_eq!(data.file_count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn adding_the_same_file_twice_stores_it_once() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id_1 = data.add_file_from_disk("First", &test_files.join("swords/tall.png"))?;
+        let id_2 = data.add_file_from_disk("Second", &test_files.join("swords/tall.png"))?;
+
+        assert_eq!(id_1, id_2);
+        assert_eq!(data.file_count(), 1);
+        assert!(data.get_file_info(id_1).unwrap().content_hash().is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn different_files_are_stored_separately() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id_1 = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let id_2 = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+
+        assert_ne!(id_1, id_2);
+        assert_eq!(data.file_count(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn replace_asset_file() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let old = data.add_file_from_disk("Sword", &test_files.join("swords/tall.png"))?;
+        let new = data.add_file_from_disk("Better sword", &test_files.join("swords/wide.png"))?;
+        let id = data.new_asset("Sword", &[old])?;
+        data.assets.add_tag(id, "weapon");
+
+        data.replace_asset_file(id, new)?;
+        let asset = data.get_asset(id).unwrap();
+        assert_eq!(asset.title(), "Sword");
+        assert!(asset.tags().contains("weapon"));
+        assert_eq!(asset.files(), &[new]);
+        assert!(data.get_file_info(old).is_some());
+
+        let unknown: FileId = "99".parse()?;
+        assert!(matches!(
+            data.replace_asset_file(id, unknown),
+            Err(DataError::FileNotFound(_))
+        ));
+        assert_eq!(data.get_asset(id).unwrap().files(), &[new]);
+        data.remove_asset(id)?;
+        assert!(matches!(
+            data.replace_asset_file(id, old),
+            Err(DataError::AssetNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn assets_in_collections() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let file = data.add_file_from_bytes("Sword", KnownExtension::Gif, b"sword")?;
+        let sword = data.new_asset("Sword", &[file])?;
+        let axe = data.new_asset("Axe", &[file])?;
+        let weapons = data.new_collection("Weapons")?;
+        let favorites = data.new_collection("Favorites")?;
+
+        data.add_to_collection(weapons, sword)?;
+        data.add_to_collection(weapons, axe)?;
+        data.add_to_collection(weapons, axe)?;
+        data.add_to_collection(favorites, axe)?;
+        assert_eq!(
+            data.get_collection(weapons).unwrap().members(),
+            &[sword, axe]
+        );
+        assert_eq!(data.collections_with_asset(axe), vec![weapons, favorites]);
+
+        data.remove_from_collection(weapons, sword)?;
+        assert!(!data.get_collection(weapons).unwrap().contains(sword));
+        assert!(data.collections_with_asset(sword).is_empty());
+
+        // Removed assets are taken out of their collections.
+        data.remove_asset(axe)?;
+        assert!(data.collections_with_asset(axe).is_empty());
+        assert!(data.get_collection(favorites).unwrap().members().is_empty());
+
+        assert!(matches!(
+            data.add_to_collection(weapons, axe),
+            Err(DataError::AssetNotFound(_))
+        ));
+        let unknown: CollectionId = "99".parse()?;
+        assert!(matches!(
+            data.add_to_collection(unknown, sword),
+            Err(DataError::CollectionNotFound(_))
+        ));
+        assert!(matches!(
+            data.remove_from_collection(unknown, sword),
+            Err(DataError::CollectionNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn summary_covers_every_store() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let tall = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        data.add_file_from_bytes("Animated", KnownExtension::Gif, b"animated")?;
+        data.add_file_from_bytes("Vector", KnownExtension::Svg, b"<svg/>")?;
+        let asset = data.new_asset("Sword", &[tall])?;
+        data.new_asset("Other sword", &[tall])?;
+        let collection = data.new_collection("Weapons")?;
+        data.add_to_collection(collection, asset)?;
+
+        assert_eq!(
+            data.summary(),
+            Summary {
+                file_count: 3,
+                asset_count: 2,
+                collection_count: 1,
+                image_count: 2,
+                total_size_bytes: 17797 + 8 + 6,
+                by_extension: [("png", 1), ("gif", 1), ("svg", 1)]
+                    .iter()
+                    .map(|(extension, count)| (extension.to_string(), *count))
+                    .collect(),
+            }
+        );
+        assert_eq!(data.summary().by_extension, data.count_by_extension());
+
+        Ok(())
+    }
+
+    #[test]
+    fn describe_counts_every_store() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        assert_eq!(data.files.item_kind(), "file");
+        assert_eq!(data.assets.item_kind(), "asset");
+        assert_eq!(data.collections.item_kind(), "collection");
+
+        let first = data.add_file_from_bytes("First", KnownExtension::Gif, b"first")?;
+        data.add_file_from_bytes("Second", KnownExtension::Gif, b"second")?;
+        data.new_asset("Asset", &[first])?;
+        assert_eq!(data.describe(), "2 file(s), 1 asset(s), 0 collection(s)");
+
+        Ok(())
+    }
+
+    #[test]
+    fn rename_asset() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let file = data.add_file_from_disk("Sword", &test_files.join("swords/tall.png"))?;
+        let id = data.new_asset("Old", &[file])?;
+
+        data.rename_asset(id, " Tall Sword ")?;
+        let asset = data.get_asset(id).unwrap();
+        assert_eq!(asset.title(), "Tall Sword");
+        assert_eq!(asset.files(), &[file]);
+
+        data.remove_asset(id)?;
+        assert!(matches!(
+            data.rename_asset(id, "Gone"),
+            Err(DataError::AssetNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn files_in_use_can_not_be_removed() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let file = data.add_file_from_disk("Sword", &test_files.join("swords/tall.png"))?;
+        let asset_1 = data.new_asset("First", &[file])?;
+        let asset_2 = data.new_asset("Second", &[file])?;
+
+        match data.remove_file(file) {
+            Err(DataError::FileInUse { assets, .. }) => {
+                assert_eq!(assets.len(), 2);
+                assert!(assets.contains(&asset_1));
+                assert!(assets.contains(&asset_2));
+            }
+            _ => panic!("A file in use should not be removable."),
+        }
+
+        data.remove_asset(asset_1)?;
+        assert!(data.remove_file(file).is_err());
+        data.remove_asset(asset_2)?;
+        data.remove_file(file)?;
+        assert_eq!(data.file_count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_file_deletes_entry_and_file() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id = data.add_file_from_disk("Sword", &test_files.join("swords/tall.png"))?;
+        let path = file_dir.join(data.get_file_info(id).unwrap().file_name());
+        assert!(path.exists());
+
+        data.remove_file(id)?;
+
+        assert_eq!(data.file_count(), 0);
+        assert!(!path.exists());
+
+        // Removing it a second time should fail, because it is no longer known.
+        assert!(data.remove_file(id).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn clear_all_empties_every_store() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let tall = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let asset = data.new_asset("Tall sword", &[tall])?;
+        let collection = data.new_collection("Weapons")?;
+        data.add_to_collection(collection, asset)?;
+        for &kind in &StoreKind::ALL {
+            assert_eq!(data.store_count(kind), 1);
+        }
+
+        data.clear_all()?;
+
+        for &kind in &StoreKind::ALL {
+            assert_eq!(data.store_count(kind), 0);
+        }
+        assert_eq!(std::fs::read_dir(&file_dir)?.count(), 0);
+        assert_eq!(data.describe(), "0 file(s), 0 asset(s), 0 collection(s)");
+
+        Ok(())
+    }
+
+    #[test]
+    fn clear_files_deletes_everything() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let tall = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let wide = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+        let asset = data.new_asset("Tall sword", &[tall])?;
+
+        // Not while an asset uses one of them.
+        assert!(data.clear_files().is_err());
+        assert_eq!(data.file_count(), 2);
+
+        data.remove_asset(asset)?;
+        data.clear_files()?;
+        assert_eq!(data.file_count(), 0);
+        assert_eq!(std::fs::read_dir(&file_dir)?.count(), 0);
+
+        // Ids are not reused.
+        let new = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        assert_ne!(new, tall);
+        assert_ne!(new, wide);
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_file_already_gone_from_disk() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id = data.add_file_from_disk("Sword", &test_files.join("swords/tall.png"))?;
+        std::fs::remove_file(file_dir.join(data.get_file_info(id).unwrap().file_name()))?;
+
+        data.remove_file(id)?;
+        assert_eq!(data.file_count(), 0);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn save_and_load_index() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id_1 = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let id_2 = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+        data.save()?;
+
+        let mut loaded = Data::load(&save_dir, &file_dir)?;
+        assert_eq!(loaded.file_count(), 2);
+        for (id, file) in data.file_iter() {
+            let loaded_file = loaded.get_file_info(*id).unwrap();
+            assert_eq!(loaded_file.title(), file.title());
+            assert_eq!(loaded_file.extension(), file.extension());
+            assert_eq!(loaded_file.created_at(), file.created_at());
+        }
+
+        // New files should not reuse ids from before the reload.
+        let id_3 =
+            loaded.add_file_from_disk("Square", &test_files.join("swords/square_crossed.png"))?;
+        assert_ne!(id_3, id_1);
+        assert_ne!(id_3, id_2);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn failed_save_keeps_the_old_index() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        data.add_file_from_bytes("First", KnownExtension::Gif, b"first")?;
+        data.save()?;
+        assert!(!save_dir.join(INDEX_TEMP_FILE_NAME).exists());
+        let saved = std::fs::read(save_dir.join(INDEX_FILE_NAME))?;
+
+        // A directory in the way of the temporary index makes writing it fail.
+        data.add_file_from_bytes("Second", KnownExtension::Gif, b"second")?;
+        std::fs::create_dir(save_dir.join(INDEX_TEMP_FILE_NAME))?;
+        assert!(matches!(data.save(), Err(DataError::Io { .. })));
+
+        assert_eq!(std::fs::read(save_dir.join(INDEX_FILE_NAME))?, saved);
+        assert_eq!(Data::load(&save_dir, &file_dir)?.file_count(), 1);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn index_without_version_is_loaded() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+        data.add_file_from_bytes("First", KnownExtension::Gif, b"first")?;
+        data.save()?;
+
+        let index_path = save_dir.join(INDEX_FILE_NAME);
+        let mut index: serde_json::Value = serde_json::from_slice(&std::fs::read(&index_path)?)?;
+        assert_eq!(index["version"], INDEX_VERSION);
+        index.as_object_mut().unwrap().remove("version");
+        std::fs::write(&index_path, index.to_string())?;
+
+        assert_eq!(Data::load(&save_dir, &file_dir)?.file_count(), 1);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn index_from_the_future_is_rejected() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        Data::new(&save_dir, &file_dir)?.save()?;
+
+        let index_path = save_dir.join(INDEX_FILE_NAME);
+        let mut index: serde_json::Value = serde_json::from_slice(&std::fs::read(&index_path)?)?;
+        index["version"] = (INDEX_VERSION + 1).into();
+        std::fs::write(&index_path, index.to_string())?;
+
+        match Data::load(&save_dir, &file_dir) {
+            Err(e @ DataError::UnsupportedIndexVersion { .. }) => {
+                assert_eq!(
+                    e.to_string(),
+                    "The index has version 2, but only versions up to 1 can be read"
+                );
+            }
+            _ => panic!("Index from the future should be rejected"),
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn load_without_index_is_empty() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+
+        let data = Data::load(&save_dir, &file_dir)?;
+
+        assert_eq!(data.file_count(), 0);
+        assert!(save_dir.exists());
+        assert!(file_dir.exists());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn load_without_index_recovers_files() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+
+        // Not saved, so there is no index.
+        let loaded = Data::load(&save_dir, &file_dir)?;
+        assert_eq!(loaded.file_count(), 1);
+        assert_eq!(
+            loaded.get_file_info(id).unwrap().content_hash(),
+            data.get_file_info(id).unwrap().content_hash()
+        );
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "serde", feature = "image"))]
+    #[test]
+    fn builder_load_keeps_the_configuration() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        data.save()?;
+
+        let thumbnails_dir = temp.path().join("previews");
+        let mut loaded = DataBuilder::new(&save_dir, &file_dir)
+            .thumbnails_dir(&thumbnails_dir)
+            .verify_images(true)
+            .load()?;
+
+        assert_eq!(loaded.file_count(), 1);
+        let thumbnail = loaded.generate_thumbnail(id, &ThumbnailOptions::default())?;
+        assert!(thumbnail.starts_with(&thumbnails_dir));
+        let result = loaded.add_file_from_bytes("Bogus", KnownExtension::Png, b"not a png");
+        assert!(matches!(result, Err(DataError::InvalidImageContent(_))));
+
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn builder_load_without_directories() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+
+        let data = DataBuilder::new(&save_dir, &file_dir)
+            .create_dirs(false)
+            .load()?;
+
+        assert_eq!(data.file_count(), 0);
+        assert!(!file_dir.exists());
+
+        Ok(())
+    }
+
+    /// Splits a line of CSV into its fields, undoing `csv_field`.
+    fn parse_csv_line(line: &str) -> Vec<String> {
+        let mut fields = vec![String::new()];
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => {
+                    chars.next();
+                    fields.last_mut().unwrap().push('"');
+                }
+                '"' => quoted = !quoted,
+                ',' if !quoted => fields.push(String::new()),
+                c => fields.last_mut().unwrap().push(c),
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn export_csv() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let sword = data.add_file_from_bytes("Sword, \"big\"", KnownExtension::Gif, b"sword")?;
+        let shield =
+            data.add_file_from_disk("Shield", &test_files.join("swords_transparent/tall_t.png"))?;
+
+        let csv_path = temp.path().join("files.csv");
+        data.export_csv(&csv_path)?;
+        let csv = std::fs::read_to_string(&csv_path)?;
+        let rows: Vec<Vec<String>> = csv.lines().map(parse_csv_line).collect();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[0],
+            vec![
+                "id",
+                "title",
+                "extension",
+                "size_bytes",
+                "created_at",
+                "system_tags"
+            ]
+        );
+        assert_eq!(rows[1][0], sword.to_string());
+        assert_eq!(rows[1][1], "Sword, \"big\"");
+        assert_eq!(rows[1][2..4], ["gif", "5"]);
+        assert_eq!(rows[1][5], "");
+        assert_eq!(rows[2][0], shield.to_string());
+        assert_eq!(rows[2][1], "Shield");
+        assert_eq!(rows[2][2], "png");
+        let created_at: u64 = rows[2][4].parse()?;
+        assert!(created_at > 0);
+        #[cfg(feature = "image")]
+        assert_eq!(rows[2][5], "transparent");
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn export_and_import_zip() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+        data.import_directory(&Path::new(TEST_FILES_PATH).join("swords"), false)?;
+        data.add_file_from_bytes("Generated", KnownExtension::Gif, b"generated")?;
+
+        let zip_path = temp.path().join("export.zip");
+        data.export_zip(&zip_path)?;
+
+        let import_dir = temp.path().join("imported");
+        let imported = Data::import_zip(&zip_path, &import_dir, &import_dir.join("files"))?;
+
+        assert_eq!(imported.file_count(), data.file_count());
+        for (id, file) in data.file_iter() {
+            let imported_file = imported.get_file_info(*id).unwrap();
+            assert_eq!(imported_file.title(), file.title());
+            assert_eq!(imported.read_file_bytes(*id)?, data.read_file_bytes(*id)?);
+        }
+        assert!(!import_dir.join(INDEX_TEMP_FILE_NAME).exists());
+
+        Ok(())
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn import_zip_keeps_an_existing_index() -> Result<()> {
+        let (temp, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+        data.add_file_from_bytes("Generated", KnownExtension::Gif, b"generated")?;
+        let zip_path = temp.path().join("export.zip");
+        data.export_zip(&zip_path)?;
+
+        let import_dir = temp.path().join("imported");
+        let import_files_dir = import_dir.join("files");
+        Data::new(&import_dir, &import_files_dir)?.save()?;
+        let index = std::fs::read(import_dir.join(INDEX_FILE_NAME))?;
+
+        let result = Data::import_zip(&zip_path, &import_dir, &import_files_dir);
+
+        assert!(
+            matches!(result, Err(DataError::IndexExists(path)) if path == import_dir.join(INDEX_FILE_NAME))
+        );
+        assert_eq!(std::fs::read(import_dir.join(INDEX_FILE_NAME))?, index);
+        assert_eq!(std::fs::read_dir(&import_files_dir)?.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn reconcile_disk_renames_misnamed_files() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let misnamed = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let missing = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+        data.add_file_from_bytes("Fine", KnownExtension::Gif, b"fine")?;
+
+        let expected_path = data.file_path(misnamed).unwrap();
+        let stray_path = file_dir.join("renamed.png");
+        std::fs::rename(&expected_path, &stray_path)?;
+        std::fs::remove_file(data.file_path(missing).unwrap())?;
+        let unknown_path = file_dir.join("unknown.txt");
+        std::fs::write(&unknown_path, "unknown")?;
+
+        // Only a report, nothing is moved yet.
+        let preview = data.reconcile_disk(false)?;
+        assert_eq!(preview.fixed, vec![(stray_path.clone(), misnamed)]);
+        assert_eq!(preview.missing, vec![missing]);
+        assert_eq!(preview.unexpected, vec![unknown_path.clone()]);
+        assert!(stray_path.exists());
+
+        let report = data.reconcile_disk(true)?;
+        assert_eq!(report.fixed, preview.fixed);
+        assert!(!stray_path.exists());
+        assert_eq!(
+            std::fs::read(&expected_path)?,
+            std::fs::read(test_files.join("swords/tall.png"))?
+        );
+
+        let after = data.reconcile_disk(true)?;
+        assert!(after.fixed.is_empty());
+        assert_eq!(after.missing, vec![missing]);
+        assert_eq!(after.unexpected, vec![unknown_path]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn find_duplicate_files_groups_same_contents() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let tall = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+        let copy: FileId = "10".parse()?;
+        data.add_file_with_id(copy, "Tall copy", &test_files.join("swords/tall.png"))?;
+
+        assert_eq!(data.find_duplicate_files(), vec![vec![tall, copy]]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_integrity_finds_dangling_and_missing_files() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let kept = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let deleted = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+        data.new_asset("Fine", &[kept])?;
+        assert!(data.check_integrity().is_empty());
+
+        // Data itself does not allow this, but a corrupt index could.
+        let unknown: FileId = "99".parse()?;
+        let dangling = data.assets.new_asset("Dangling", &[kept, unknown])?;
+        std::fs::remove_file(data.file_path(deleted).unwrap())?;
+
+        assert_eq!(
+            data.check_integrity(),
+            vec![
+                IntegrityIssue::DanglingFile {
+                    asset: dangling,
+                    file: unknown
+                },
+                IntegrityIssue::MissingOnDisk(deleted),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn verify_file_detects_changed_contents() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let corrupted = data.add_file_from_disk("Tall", &test_files.join("swords/tall.png"))?;
+        let deleted = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+        assert!(data.verify_file(corrupted)?);
+
+        std::fs::write(data.file_path(corrupted).unwrap(), b"bit rot")?;
+        std::fs::remove_file(data.file_path(deleted).unwrap())?;
+
+        assert!(!data.verify_file(corrupted)?);
+        assert!(matches!(
+            data.verify_file(deleted),
+            Err(DataError::MissingOnDisk(id)) if id == deleted
+        ));
+        assert!(matches!(
+            data.verify_file("99".parse()?),
+            Err(DataError::FileNotFound(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn add_file_with_explicit_id() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let id: FileId = "5".parse()?;
+        data.add_file_with_id(id, "Tall", &test_files.join("swords/tall.png"))?;
+        assert_eq!(data.get_file_info(id).unwrap().title(), "Tall");
+        assert_eq!(
+            data.read_file_bytes(id)?,
+            std::fs::read(test_files.join("swords/tall.png"))?
+        );
+
+        let taken = data.add_file_with_id(id, "Wide", &test_files.join("swords/wide.png"));
+        assert!(matches!(taken, Err(DataError::Store(_))));
+        assert_eq!(data.get_file_info(id).unwrap().title(), "Tall");
+
+        // Newly added files come after the explicit one.
+        let next = data.add_file_from_disk("Wide", &test_files.join("swords/wide.png"))?;
+        assert_eq!(next, "6".parse()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_extensions_are_rejected() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let test_files = Path::new(TEST_FILES_PATH);
+        let result = data.add_file_from_disk("Source", &test_files.join("swords_sources/tall.xcf"));
+
+        assert!(
+            matches!(result, Err(DataError::UnknownExtension(extension)) if extension == "xcf")
+        );
+        assert_eq!(data.file_count(), 0);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn add_file_from_disk_async_copies_file() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let source = Path::new(TEST_FILES_PATH).join("swords/tall.png");
+        let id = data.add_file_from_disk_async("Tall", &source).await?;
+
+        let file = data.get_file_info(id).unwrap();
+        assert_eq!(file.title(), "Tall");
+        assert_eq!(file.size_bytes(), 17797);
+        assert_eq!(
+            std::fs::read(file_dir.join(file.file_name()))?,
+            std::fs::read(&source)?
+        );
+
+        // The same contents again give back the same file.
+        assert_eq!(data.add_file_from_disk_async("Again", &source).await?, id);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn failed_async_copy_leaves_no_entry() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        std::fs::create_dir(file_dir.join("0.png"))?;
+        let source = Path::new(TEST_FILES_PATH).join("swords/tall.png");
+        let result = data.add_file_from_disk_async("Sword", &source).await;
+
+        assert!(matches!(result, Err(DataError::CopyFailed { .. })));
+        assert_eq!(data.file_count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stale_files_are_not_overwritten() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        // Left behind by a previous run that did not save its index.
+        let stale = file_dir.join("0.png");
+        std::fs::write(&stale, "stale")?;
+        let test_files = Path::new(TEST_FILES_PATH);
+        let result = data.add_file_from_disk("Sword", &test_files.join("swords/tall.png"));
+
+        assert!(matches!(result, Err(DataError::StaleFile(path)) if path == stale));
+        assert_eq!(data.file_count(), 0);
+        assert_eq!(std::fs::read(&stale)?, b"stale");
+
+        // The next try gets a new id, so it does not run into the stale file.
+        let id = data.add_file_from_disk("Sword", &test_files.join("swords/tall.png"))?;
+        assert_eq!(id, "1".parse()?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn leftover_file_with_same_contents_is_reused() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        let source = Path::new(TEST_FILES_PATH).join("swords/tall.png");
+        std::fs::copy(&source, file_dir.join("0.png"))?;
+        let id =
+            data.add_file_from_bytes("Sword", KnownExtension::Png, &std::fs::read(&source)?)?;
+        assert_eq!(id, "0".parse()?);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn vector_files_are_not_decoded() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = DataBuilder::new(&save_dir, &file_dir)
+            .verify_images(true)
+            .build()?;
+
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="20"/>"#;
+        let id = data.add_file_from_bytes("Vector", KnownExtension::Svg, svg)?;
+
+        let file = data.get_file_info(id).unwrap();
+        assert_eq!(file.extension(), &KnownExtension::Svg);
+        assert_eq!(file.dimensions(), None);
+        assert!(file.system_tags().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_copy_leaves_no_entry() -> Result<()> {
+        let (_, save_dir, file_dir) = setup_temp_directory();
+        let mut data = Data::new(&save_dir, &file_dir)?;
+
+        // Something is in the way of where the file should go.
+        std::fs::create_dir(file_dir.join("0.png"))?;
+        let test_files = Path::new(TEST_FILES_PATH);
+        let result = data.add_file_from_disk("Sword", &test_files.join("swords/tall.png"));
+
+        assert!(matches!(result, Err(DataError::CopyFailed { .. })));
+        assert_eq!(data.file_count(), 0);
 
         Ok(())
     }
 
-    // TODO: add a check for adding nonexisting asset files
-    //       and ones with an extension we dont recognise.
+    // TODO: add a check for adding nonexisting asset files.
 
     /// Sets up a temporary directory for use in the other tests
     /// The directory will disappear as soon as the directory handle goes out of scope.
@@ -0,0 +1,103 @@
+use crate::stores::asset_store::AssetId;
+use crate::stores::collection_store::CollectionId;
+use crate::stores::file_store::FileId;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Everything that can go wrong when working with `Data`.
+#[derive(Debug, Error)]
+pub enum DataError {
+    /// The extension of a file that we don't know how to deal with.
+    #[error("Extension \"{0}\" is not known")]
+    UnknownExtension(String),
+    #[error("There is no file with id {0}")]
+    FileNotFound(FileId),
+    #[error("There is no asset with id {0}")]
+    AssetNotFound(AssetId),
+    #[error("There is no collection with id {0}")]
+    CollectionNotFound(CollectionId),
+    /// The file is in the index, but not in the files directory.
+    #[error("File {0} is missing from the files directory")]
+    MissingOnDisk(FileId),
+    /// The file was added before content hashes were recorded, so it can't be verified.
+    #[error("File {0} has no recorded content hash")]
+    NoContentHash(FileId),
+    #[error("Titles can not be empty")]
+    EmptyTitle,
+    /// Titles are shown and exported, so they can't have control characters like newlines.
+    #[error("Title {0:?} contains control characters")]
+    InvalidTitle(String),
+    /// The file can't be removed, because these assets still refer to it.
+    #[error(
+        "File {file} is still used by assets {}",
+        .assets.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    FileInUse { file: FileId, assets: Vec<AssetId> },
+    #[error(
+        "Could not copy \"{}\" to the file storage at \"{}\"",
+        .src.display(),
+        .dest.display()
+    )]
+    CopyFailed {
+        src: PathBuf,
+        dest: PathBuf,
+        source: std::io::Error,
+    },
+    /// A different file is already where a new file should go,
+    /// which means the index and the files directory are out of sync.
+    #[error("There already is a different file at \"{}\"", .0.display())]
+    StaleFile(PathBuf),
+    #[error("Could not access \"{}\"", .path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// Content that does not match its image extension, when verifying imports.
+    #[cfg(feature = "image")]
+    #[error("\"{}\" is not a valid image of the type its extension claims", .0.display())]
+    NotAnImage(PathBuf),
+    /// Like `NotAnImage`, for content from memory or a stream, which only has a title.
+    #[cfg(feature = "image")]
+    #[error("The content for \"{0}\" is not a valid image of the type its extension claims")]
+    InvalidImageContent(String),
+    /// Images can only be written in a format that stores pixels.
+    #[cfg(feature = "image")]
+    #[error("\"{0}\" is not a raster image format")]
+    NotARasterFormat(&'static str),
+    #[cfg(feature = "image")]
+    #[error("Jpeg quality has to be between 1 and 100, not {0}")]
+    InvalidJpegQuality(u8),
+    #[cfg(feature = "image")]
+    #[error("Could not process image \"{}\"", .path.display())]
+    Image {
+        path: PathBuf,
+        source: image::ImageError,
+    },
+    #[cfg(feature = "serde")]
+    #[error("Could not read or write the index")]
+    Index(#[from] serde_json::Error),
+    /// The index was saved by a newer version, which may have changed what it means.
+    #[cfg(feature = "serde")]
+    #[error("The index has version {found}, but only versions up to {supported} can be read")]
+    UnsupportedIndexVersion { found: u32, supported: u32 },
+    #[cfg(feature = "zip")]
+    #[error("Could not read or write the zip file")]
+    Zip(#[from] zip::result::ZipError),
+    /// Importing would replace the index of what is already stored there.
+    #[cfg(feature = "zip")]
+    #[error("There already is an index at \"{}\"", .0.display())]
+    IndexExists(PathBuf),
+    /// Something went wrong inside one of the stores, like running out of ids.
+    #[error(transparent)]
+    Store(#[from] anyhow::Error),
+}
+
+impl DataError {
+    /// For use with `map_err`, to attach the path to an io error.
+    pub fn io(path: &Path) -> impl FnOnce(std::io::Error) -> DataError + '_ {
+        move |source| DataError::Io {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+}
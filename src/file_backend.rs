@@ -0,0 +1,288 @@
+use crate::content_hash;
+use std::collections::HashMap;
+use std::io;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Where `Data` keeps the contents of the stored files.
+/// Paths are the full paths of the files in the files directory, see `Data::file_path`.
+pub trait FileBackend {
+    fn create_dir_all(&mut self, dir: &Path) -> io::Result<()>;
+
+    /// Copies a file from disk into the backend. Returns the number of bytes copied.
+    fn copy_from_disk(&mut self, source: &Path, dest: &Path) -> io::Result<u64>;
+
+    fn write(&mut self, dest: &Path, bytes: &[u8]) -> io::Result<()>;
+
+    /// Writes everything from the reader to `dest`. Returns the number of bytes written.
+    /// When this fails, part of the file may have been written already.
+    fn write_from_reader(&mut self, dest: &Path, reader: &mut dyn Read) -> io::Result<u64>;
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Fails with `io::ErrorKind::NotFound` when there is no file at the path.
+    fn remove(&mut self, path: &Path) -> io::Result<()>;
+
+    fn is_file(&self, path: &Path) -> bool;
+
+    /// The hash of the contents of a file, see `content_hash::hash_file`.
+    fn content_hash(&self, path: &Path) -> io::Result<String>;
+
+    /// Moves a file within the backend, like to a new files directory.
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Removes an empty directory. Fails when there is still something in it.
+    fn remove_dir(&mut self, dir: &Path) -> io::Result<()>;
+
+    /// The files directly in `dir`, in no particular order.
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>>;
+
+    /// When the file was last written.
+    fn modified(&self, path: &Path) -> io::Result<SystemTime>;
+
+    /// Whether the paths are real files on disk, which can also be used without the backend,
+    /// like from other threads. Defaults to false.
+    fn is_on_disk(&self) -> bool {
+        false
+    }
+}
+
+/// Keeps the files on disk. This is what `Data` uses unless told otherwise.
+pub struct DiskBackend;
+
+impl FileBackend for DiskBackend {
+    fn create_dir_all(&mut self, dir: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(dir)
+    }
+
+    fn copy_from_disk(&mut self, source: &Path, dest: &Path) -> io::Result<u64> {
+        std::fs::copy(source, dest)
+    }
+
+    fn write(&mut self, dest: &Path, bytes: &[u8]) -> io::Result<()> {
+        std::fs::write(dest, bytes)
+    }
+
+    fn write_from_reader(&mut self, dest: &Path, reader: &mut dyn Read) -> io::Result<u64> {
+        let mut file = std::fs::File::create(dest)?;
+        io::copy(reader, &mut file)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn remove(&mut self, path: &Path) -> io::Result<()> {
+        std::fs::remove_file(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn content_hash(&self, path: &Path) -> io::Result<String> {
+        content_hash::hash_file(path)
+    }
+
+    /// Also works when `to` is on a different file system, by copying the file over.
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        if std::fs::rename(from, to).is_ok() {
+            return Ok(());
+        }
+
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)
+    }
+
+    fn remove_dir(&mut self, dir: &Path) -> io::Result<()> {
+        std::fs::remove_dir(dir)
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+        Ok(files)
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        std::fs::metadata(path)?.modified()
+    }
+
+    fn is_on_disk(&self) -> bool {
+        true
+    }
+}
+
+/// Keeps the files in memory, so nothing is written to disk. Useful for tests.
+/// There are no directories, so creating and removing them always works.
+#[derive(Default)]
+pub struct InMemoryBackend {
+    files: HashMap<PathBuf, MemoryFile>,
+}
+
+struct MemoryFile {
+    bytes: Vec<u8>,
+    modified: SystemTime,
+}
+
+impl InMemoryBackend {
+    pub fn new() -> InMemoryBackend {
+        InMemoryBackend::default()
+    }
+
+    fn insert(&mut self, path: &Path, bytes: Vec<u8>) {
+        let file = MemoryFile {
+            bytes,
+            modified: SystemTime::now(),
+        };
+        self.files.insert(path.to_path_buf(), file);
+    }
+
+    fn get(&self, path: &Path) -> io::Result<&MemoryFile> {
+        self.files.get(path).ok_or_else(|| not_found(path))
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("\"{}\" is not in memory", path.display()),
+    )
+}
+
+impl FileBackend for InMemoryBackend {
+    fn create_dir_all(&mut self, _dir: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn copy_from_disk(&mut self, source: &Path, dest: &Path) -> io::Result<u64> {
+        let bytes = std::fs::read(source)?;
+        let size = bytes.len() as u64;
+        self.insert(dest, bytes);
+        Ok(size)
+    }
+
+    fn write(&mut self, dest: &Path, bytes: &[u8]) -> io::Result<()> {
+        self.insert(dest, bytes.to_vec());
+        Ok(())
+    }
+
+    fn write_from_reader(&mut self, dest: &Path, reader: &mut dyn Read) -> io::Result<u64> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let size = bytes.len() as u64;
+        self.insert(dest, bytes);
+        Ok(size)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.get(path).map(|file| file.bytes.clone())
+    }
+
+    fn remove(&mut self, path: &Path) -> io::Result<()> {
+        self.files
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(path)
+    }
+
+    fn content_hash(&self, path: &Path) -> io::Result<String> {
+        self.get(path)
+            .map(|file| content_hash::hash_bytes(&file.bytes))
+    }
+
+    fn rename(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        let file = self.files.remove(from).ok_or_else(|| not_found(from))?;
+        self.files.insert(to.to_path_buf(), file);
+        Ok(())
+    }
+
+    fn remove_dir(&mut self, _dir: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(self
+            .files
+            .keys()
+            .filter(|path| path.parent() == Some(dir))
+            .cloned()
+            .collect())
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        self.get(path).map(|file| file.modified)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn in_memory_files_can_be_written_read_and_removed() {
+        let mut backend = InMemoryBackend::new();
+        let path = Path::new("/nowhere/0.png");
+
+        backend.write(path, b"contents").unwrap();
+        assert!(backend.is_file(path));
+        assert_eq!(backend.read(path).unwrap(), b"contents");
+        assert_eq!(
+            backend.content_hash(path).unwrap(),
+            content_hash::hash_bytes(b"contents")
+        );
+
+        backend.remove(path).unwrap();
+        assert!(!backend.is_file(path));
+        assert_eq!(
+            backend.remove(path).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(backend.read(path).is_err());
+        assert!(!Path::new("/nowhere").exists());
+    }
+
+    #[test]
+    fn in_memory_files_can_be_renamed_and_listed() {
+        let mut backend = InMemoryBackend::new();
+        let old = Path::new("/nowhere/0.png");
+        let new = Path::new("/elsewhere/0.png");
+        backend.write(old, b"contents").unwrap();
+        backend
+            .write(Path::new("/nowhere/deeper/1.png"), b"deeper")
+            .unwrap();
+        let modified = backend.modified(old).unwrap();
+
+        backend.rename(old, new).unwrap();
+
+        assert!(!backend.is_file(old));
+        assert_eq!(backend.read(new).unwrap(), b"contents");
+        assert_eq!(backend.modified(new).unwrap(), modified);
+        assert_eq!(backend.list(Path::new("/elsewhere")).unwrap(), vec![new]);
+        assert!(backend.list(Path::new("/nowhere")).unwrap().is_empty());
+        assert!(backend.rename(old, new).is_err());
+    }
+
+    #[test]
+    fn copy_from_disk_into_memory() {
+        let mut backend = InMemoryBackend::new();
+        let source = Path::new("tests/files/swords/tall.png");
+        let dest = Path::new("/nowhere/0.png");
+
+        assert_eq!(backend.copy_from_disk(source, dest).unwrap(), 17797);
+        assert_eq!(backend.read(dest).unwrap(), std::fs::read(source).unwrap());
+        assert!(backend
+            .copy_from_disk(Path::new("tests/files/missing.png"), dest)
+            .is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use crate::stores::file_store::KnownExtension;
+use anyhow::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::error::{ImageFormatHint, UnsupportedError};
+use image::{DynamicImage, ImageError, ImageFormat, ImageReader};
+use std::io::{BufRead, BufWriter, Cursor, Seek, Write};
+use std::path::Path;
+
+/// Decodes the image at the given path, and checks whether any of its pixels
+/// are not fully opaque.
+pub fn has_transparency(path: &Path) -> Result<bool> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Could not read image \"{}\"", path.display()))?;
+    has_transparency_bytes(&bytes)
+        .with_context(|| format!("Could not decode image \"{}\"", path.display()))
+}
+
+/// Like `has_transparency`, but for an image in memory.
+pub fn has_transparency_bytes(bytes: &[u8]) -> image::ImageResult<bool> {
+    let image = image::load_from_memory(bytes)?;
+
+    // Without an alpha channel, there can be no transparent pixels.
+    if !image.color().has_alpha() {
+        return Ok(false);
+    }
+
+    Ok(image.to_rgba8().pixels().any(|pixel| pixel[3] < u8::MAX))
+}
+
+/// Reads the width and height of the image at the given path.
+/// Only the header is decoded, not the whole image.
+pub fn dimensions(path: &Path) -> Result<(u32, u32)> {
+    image::image_dimensions(path)
+        .with_context(|| format!("Could not decode image \"{}\"", path.display()))
+}
+
+/// Like `dimensions`, but for an image in memory.
+pub fn dimensions_bytes(bytes: &[u8]) -> image::ImageResult<(u32, u32)> {
+    ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()?
+        .into_dimensions()
+}
+
+/// Difference hash of an image in memory: for a tiny grayscale version of the image,
+/// every bit says whether a pixel is brighter than the one to its right.
+/// Images that look alike, like resized or re-encoded copies, have hashes that differ in
+/// only a few bits, see `hash_distance`.
+#[cfg(feature = "phash")]
+pub fn difference_hash_bytes(bytes: &[u8]) -> image::ImageResult<u64> {
+    let small = image::load_from_memory(bytes)?
+        .resize_exact(9, 8, image::imageops::FilterType::Triangle)
+        .to_luma8();
+
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if small.get_pixel(x, y)[0] > small.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    Ok(hash)
+}
+
+/// In how many bits two perceptual hashes differ, see `difference_hash_bytes`.
+#[cfg(feature = "phash")]
+pub fn hash_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Checks whether the file holds an image of the type that the extension claims,
+/// by decoding its header.
+pub fn is_valid_file(path: &Path, extension: &KnownExtension) -> bool {
+    match ImageReader::open(path) {
+        Ok(reader) => is_valid(reader, extension),
+        Err(_) => false,
+    }
+}
+
+/// Like `is_valid_file`, but for an image in memory.
+pub fn is_valid_bytes(bytes: &[u8], extension: &KnownExtension) -> bool {
+    is_valid(ImageReader::new(Cursor::new(bytes)), extension)
+}
+
+fn is_valid<R: BufRead + Seek>(reader: ImageReader<R>, extension: &KnownExtension) -> bool {
+    let expected = ImageFormat::from_extension(extension.to_str());
+    match reader.with_guessed_format() {
+        Ok(reader) => reader.format() == expected && reader.into_dimensions().is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Scales the image in `source` so that its longest side is `max_dim`, keeping the aspect ratio,
+/// and writes it to `dest` in the given format.
+/// The quality, from 1 to 100, is only used for jpeg.
+pub fn write_thumbnail(
+    source: &[u8],
+    dest: &Path,
+    max_dim: u32,
+    format: &KnownExtension,
+    jpeg_quality: u8,
+) -> image::ImageResult<()> {
+    let image = image::load_from_memory(source)?;
+    let thumbnail = image.resize(max_dim, max_dim, image::imageops::FilterType::Triangle);
+
+    let mut writer = BufWriter::new(std::fs::File::create(dest)?);
+    encode(&thumbnail, &mut writer, format, jpeg_quality)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Decodes an image in memory, and encodes it again in the given format.
+/// The quality, from 1 to 100, is only used for jpeg.
+pub fn convert_bytes(
+    bytes: &[u8],
+    format: &KnownExtension,
+    jpeg_quality: u8,
+) -> image::ImageResult<Vec<u8>> {
+    let image = image::load_from_memory(bytes)?;
+
+    let mut converted = Cursor::new(Vec::new());
+    encode(&image, &mut converted, format, jpeg_quality)?;
+    Ok(converted.into_inner())
+}
+
+fn encode<W: Write + Seek>(
+    image: &DynamicImage,
+    writer: &mut W,
+    format: &KnownExtension,
+    jpeg_quality: u8,
+) -> image::ImageResult<()> {
+    let image_format = ImageFormat::from_extension(format.to_str()).ok_or_else(|| {
+        ImageError::Unsupported(UnsupportedError::from(ImageFormatHint::Name(
+            format.to_str().to_string(),
+        )))
+    })?;
+
+    if image_format == ImageFormat::Jpeg {
+        // Jpeg has no alpha channel.
+        DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_with_encoder(JpegEncoder::new_with_quality(writer, jpeg_quality))
+    } else {
+        image.write_to(writer, image_format)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TEST_FILES_PATH: &str = "tests/files";
+
+    #[test]
+    fn opaque_images_are_not_transparent() {
+        let test_files = Path::new(TEST_FILES_PATH);
+
+        assert!(!has_transparency(&test_files.join("swords/tall.png")).unwrap());
+        assert!(!has_transparency(&test_files.join("swords/wide.png")).unwrap());
+    }
+
+    #[test]
+    fn transparent_images_are_transparent() {
+        let test_files = Path::new(TEST_FILES_PATH);
+
+        assert!(has_transparency(&test_files.join("swords_transparent/tall_t.png")).unwrap());
+        assert!(has_transparency(&test_files.join("swords_transparent/wide_t.png")).unwrap());
+    }
+
+    #[test]
+    fn images_in_memory() {
+        let test_files = Path::new(TEST_FILES_PATH);
+        let opaque = std::fs::read(test_files.join("swords/tall.png")).unwrap();
+        let transparent = std::fs::read(test_files.join("swords_transparent/wide_t.png")).unwrap();
+
+        assert!(!has_transparency_bytes(&opaque).unwrap());
+        assert!(has_transparency_bytes(&transparent).unwrap());
+        assert_eq!(dimensions_bytes(&opaque).unwrap(), (350, 600));
+        assert!(has_transparency_bytes(b"not an image").is_err());
+        assert!(dimensions_bytes(b"not an image").is_err());
+    }
+
+    #[test]
+    fn icons_can_be_decoded() {
+        let mut bytes = Vec::new();
+        image::DynamicImage::new_rgba8(16, 32)
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Ico)
+            .unwrap();
+
+        assert_eq!(dimensions_bytes(&bytes).unwrap(), (16, 32));
+        assert!(has_transparency_bytes(&bytes).unwrap());
+        assert!(is_valid_bytes(&bytes, &KnownExtension::Ico));
+        assert!(!is_valid_bytes(&bytes, &KnownExtension::Png));
+    }
+
+    #[cfg(feature = "phash")]
+    #[test]
+    fn resized_images_have_close_hashes() {
+        let test_files = Path::new(TEST_FILES_PATH);
+        let tall = std::fs::read(test_files.join("swords/tall.png")).unwrap();
+        let mut resized = Vec::new();
+        image::load_from_memory(&tall)
+            .unwrap()
+            .resize(300, 300, image::imageops::FilterType::Triangle)
+            .write_to(&mut Cursor::new(&mut resized), ImageFormat::Png)
+            .unwrap();
+        let crossed = std::fs::read(test_files.join("swords/square_crossed.png")).unwrap();
+
+        let hash = difference_hash_bytes(&tall).unwrap();
+        assert!(hash_distance(hash, difference_hash_bytes(&resized).unwrap()) <= 5);
+        assert!(hash_distance(hash, difference_hash_bytes(&crossed).unwrap()) > 10);
+        assert!(difference_hash_bytes(b"not an image").is_err());
+    }
+
+    #[test]
+    fn non_images_cannot_be_decoded() {
+        let test_files = Path::new(TEST_FILES_PATH);
+
+        assert!(has_transparency(&test_files.join("swords_sources/tall.xcf")).is_err());
+        assert!(dimensions(&test_files.join("swords_sources/tall.xcf")).is_err());
+    }
+
+    #[test]
+    fn thumbnails_keep_aspect_ratio() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dest = tempdir.path().join("thumb.png");
+
+        write_thumbnail(
+            &std::fs::read(Path::new(TEST_FILES_PATH).join("swords/tall.png")).unwrap(),
+            &dest,
+            120,
+            &KnownExtension::Png,
+            100,
+        )
+        .unwrap();
+
+        assert_eq!(dimensions(&dest).unwrap(), (70, 120));
+    }
+
+    #[test]
+    fn thumbnails_can_be_jpeg() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dest = tempdir.path().join("thumb.jpg");
+
+        // The source is transparent, which jpeg can't store.
+        write_thumbnail(
+            &std::fs::read(Path::new(TEST_FILES_PATH).join("swords_transparent/tall_t.png"))
+                .unwrap(),
+            &dest,
+            120,
+            &KnownExtension::Jpeg,
+            80,
+        )
+        .unwrap();
+
+        assert!(is_valid_file(&dest, &KnownExtension::Jpeg));
+        assert_eq!(dimensions(&dest).unwrap(), (70, 120));
+    }
+
+    #[test]
+    fn valid_images_match_their_extension() {
+        let test_files = Path::new(TEST_FILES_PATH);
+        let tall = test_files.join("swords/tall.png");
+
+        assert!(is_valid_file(&tall, &KnownExtension::Png));
+        assert!(!is_valid_file(&tall, &KnownExtension::Jpeg));
+        assert!(!is_valid_file(
+            &test_files.join("swords_sources/tall.xcf"),
+            &KnownExtension::Png
+        ));
+
+        let bytes = std::fs::read(&tall).unwrap();
+        assert!(is_valid_bytes(&bytes, &KnownExtension::Png));
+        assert!(!is_valid_bytes(b"not an image", &KnownExtension::Png));
+    }
+
+    #[test]
+    fn dimensions_of_known_images() {
+        let test_files = Path::new(TEST_FILES_PATH);
+
+        assert_eq!(
+            dimensions(&test_files.join("swords/tall.png")).unwrap(),
+            (350, 600)
+        );
+        assert_eq!(
+            dimensions(&test_files.join("swords/wide.png")).unwrap(),
+            (600, 350)
+        );
+        assert_eq!(
+            dimensions(&test_files.join("swords/square_crossed.png")).unwrap(),
+            (400, 400)
+        );
+    }
+}
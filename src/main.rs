@@ -1,4 +1,9 @@
+mod content_hash;
 mod data;
+mod error;
+mod file_backend;
+#[cfg(feature = "image")]
+mod images;
 mod stores;
 
 fn main() {}
@@ -0,0 +1,1032 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+#[cfg(feature = "serde")]
+use super::file_store::unknown_time;
+use super::file_store::{validate_title, FileId};
+use super::id_allocator::{IdAllocator, SequentialAllocator};
+use super::traits::IndexedStore;
+use crate::stores::traits::{MutableStore, StoreId, StoreIter};
+use anyhow::{bail, Context, Result};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Handed out by an `AssetStore` when a new asset is added.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct AssetId(u32);
+
+impl StoreId for AssetId {
+    fn from_u32(id: u32) -> Self {
+        AssetId(id)
+    }
+
+    fn as_u32(&self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for AssetId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for AssetId {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(AssetId)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AssetStore {
+    assets: HashMap<AssetId, Asset>,
+    #[cfg_attr(feature = "serde", serde(rename = "next_id"))]
+    allocator: Box<dyn IdAllocator>,
+}
+
+impl AssetStore {
+    pub fn new() -> AssetStore {
+        AssetStore::with_capacity(0)
+    }
+
+    /// Store with room for `capacity` assets before it has to grow, for bulk imports.
+    pub fn with_capacity(capacity: usize) -> AssetStore {
+        AssetStore {
+            assets: HashMap::with_capacity(capacity),
+            allocator: Box::new(SequentialAllocator::new()),
+        }
+    }
+
+    /// Store that hands out its ids with the given allocator, instead of counting up from 0.
+    pub fn with_allocator(allocator: Box<dyn IdAllocator>) -> AssetStore {
+        AssetStore {
+            assets: HashMap::new(),
+            allocator,
+        }
+    }
+
+    /// Replaces how ids are handed out from now on, like for a store that was just loaded.
+    /// Ids that are already in use are skipped, whatever the allocator says.
+    pub fn set_allocator(&mut self, allocator: Box<dyn IdAllocator>) {
+        self.allocator = allocator;
+    }
+
+    /// Store that starts handing out ids at `next_id`.
+    #[cfg(test)]
+    fn with_next_id(next_id: u32) -> AssetStore {
+        AssetStore::with_allocator(Box::new(SequentialAllocator::starting_at(next_id)))
+    }
+
+    /// Creates a new asset that refers to the given files, in order.
+    /// Does not check whether the files actually exist, that is up to the caller.
+    /// Will return an error when a file is given more than once,
+    /// or when there are no more ids left to hand out.
+    pub fn new_asset(&mut self, title: &str, files: &[FileId]) -> Result<AssetId> {
+        self.insert(Asset {
+            // Replaced by the id that `insert` hands out.
+            id: AssetId(0),
+            title: title.to_string(),
+            files: files.to_vec(),
+            tags: HashSet::new(),
+            description: String::new(),
+            archived: false,
+            modified_at: SystemTime::now(),
+        })
+    }
+
+    /// Changes the title of an asset. Surrounding whitespace is removed.
+    /// Returns false if the asset does not exist, or if the title is not valid,
+    /// see `validate_title`.
+    pub fn set_title(&mut self, id: AssetId, title: &str) -> bool {
+        let title = match validate_title(title) {
+            Ok(title) => title,
+            Err(_) => return false,
+        };
+        match self.assets.get_mut(&id) {
+            Some(asset) => {
+                asset.title = title.to_string();
+                asset.modified_at = SystemTime::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds a file to the end of an asset's files.
+    /// Returns false if the asset does not exist, or if it already has the file.
+    pub fn add_file_to_asset(&mut self, id: AssetId, file: FileId) -> bool {
+        match self.assets.get_mut(&id) {
+            Some(asset) if !asset.files.contains(&file) => {
+                asset.files.push(file);
+                asset.modified_at = SystemTime::now();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Makes the file the asset's primary file, by putting it first.
+    /// If the asset already has the file, it is moved to the front.
+    /// Returns whether the asset exists.
+    pub fn set_primary_file(&mut self, id: AssetId, file: FileId) -> bool {
+        match self.assets.get_mut(&id) {
+            Some(asset) => {
+                asset.files.retain(|&f| f != file);
+                asset.files.insert(0, file);
+                asset.modified_at = SystemTime::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the asset's primary file, for example with a better version of the same image.
+    /// Unlike `set_primary_file`, which keeps the old primary file as a second one,
+    /// the old primary file is dropped from the asset.
+    /// The other files are kept, an asset without files gets this as its only file.
+    /// Returns whether the asset exists.
+    pub fn set_file(&mut self, id: AssetId, file: FileId) -> bool {
+        match self.assets.get_mut(&id) {
+            Some(asset) => {
+                // Already the primary file, replacing it with itself would drop nothing.
+                if asset.files.first() == Some(&file) {
+                    return true;
+                }
+
+                asset.files.retain(|&f| f != file);
+                match asset.files.first_mut() {
+                    Some(primary) => *primary = file,
+                    None => asset.files.push(file),
+                }
+                asset.modified_at = SystemTime::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a file from an asset, keeping the order of the other files.
+    /// Returns whether the asset had the file.
+    pub fn remove_file_from_asset(&mut self, id: AssetId, file: FileId) -> bool {
+        match self.assets.get_mut(&id) {
+            Some(asset) => {
+                let count = asset.files.len();
+                asset.files.retain(|&f| f != file);
+                if asset.files.len() == count {
+                    return false;
+                }
+                asset.modified_at = SystemTime::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns all the assets that refer to the given file.
+    pub fn assets_using_file(&self, file: FileId) -> Vec<AssetId> {
+        self.assets
+            .values()
+            .filter(|asset| asset.files.contains(&file))
+            .map(|asset| asset.id)
+            .collect()
+    }
+
+    /// Returns every file that at least one asset refers to.
+    /// Cheaper than `assets_using_file` for each file, since the assets are only gone over once.
+    pub fn used_files(&self) -> HashSet<FileId> {
+        self.assets
+            .values()
+            .flat_map(|asset| asset.files.iter().copied())
+            .collect()
+    }
+
+    /// Sets the free text description of an asset. An empty description clears it.
+    /// Returns whether the asset exists.
+    pub fn set_description(&mut self, id: AssetId, description: &str) -> bool {
+        match self.assets.get_mut(&id) {
+            Some(asset) => {
+                asset.description = description.to_string();
+                asset.modified_at = SystemTime::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Archives an asset, which hides it from listings without removing it,
+    /// or brings it back. Returns whether the asset exists.
+    pub fn set_archived(&mut self, id: AssetId, archived: bool) -> bool {
+        match self.assets.get_mut(&id) {
+            Some(asset) => {
+                if asset.archived != archived {
+                    asset.archived = archived;
+                    asset.modified_at = SystemTime::now();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds a tag to an asset. Tags are normalized, so "Sword " and "sword" are the same tag.
+    /// Returns whether the tag was added: false if the asset does not exist, if the tag is empty,
+    /// or if the asset already has it.
+    pub fn add_tag(&mut self, id: AssetId, tag: &str) -> bool {
+        let tag = normalize_tag(tag);
+        if tag.is_empty() {
+            return false;
+        }
+
+        match self.assets.get_mut(&id) {
+            Some(asset) => {
+                let added = asset.tags.insert(tag);
+                if added {
+                    asset.modified_at = SystemTime::now();
+                }
+                added
+            }
+            None => false,
+        }
+    }
+
+    /// Adds a tag to many assets at once, like a selection. The tag is normalized like in
+    /// `add_tag`. Unknown ids are skipped.
+    /// Returns how many assets got the tag, not counting the ones that already had it.
+    pub fn add_tag_to_many(&mut self, ids: &[AssetId], tag: &str) -> usize {
+        ids.iter().filter(|&&id| self.add_tag(id, tag)).count()
+    }
+
+    /// Removes a tag from many assets at once. Unknown ids are skipped.
+    /// Returns how many assets had the tag.
+    pub fn remove_tag_from_many(&mut self, ids: &[AssetId], tag: &str) -> usize {
+        ids.iter().filter(|&&id| self.remove_tag(id, tag)).count()
+    }
+
+    /// Removes a tag from an asset.
+    /// Returns whether the asset had the tag.
+    pub fn remove_tag(&mut self, id: AssetId, tag: &str) -> bool {
+        match self.assets.get_mut(&id) {
+            Some(asset) => {
+                let removed = asset.tags.remove(&normalize_tag(tag));
+                if removed {
+                    asset.modified_at = SystemTime::now();
+                }
+                removed
+            }
+            None => false,
+        }
+    }
+
+    /// Returns all the assets that have the given tag.
+    pub fn find_by_tag(&self, tag: &str) -> Vec<AssetId> {
+        let tag = normalize_tag(tag);
+        self.assets
+            .values()
+            .filter(|asset| asset.tags.contains(&tag))
+            .map(|asset| asset.id)
+            .collect()
+    }
+
+    /// Returns the assets that have all, or any, of the given tags. The ids are sorted.
+    /// Without any tags, `TagMatch::All` gives every asset and `TagMatch::Any` gives none.
+    pub fn find_by_tags(&self, tags: &[&str], mode: TagMatch) -> Vec<AssetId> {
+        let tags: Vec<String> = tags.iter().map(|tag| normalize_tag(tag)).collect();
+        let mut found: Vec<AssetId> = self
+            .assets
+            .values()
+            .filter(|asset| match mode {
+                TagMatch::All => tags.iter().all(|tag| asset.tags.contains(tag)),
+                TagMatch::Any => tags.iter().any(|tag| asset.tags.contains(tag)),
+            })
+            .map(|asset| asset.id)
+            .collect();
+
+        found.sort();
+        found
+    }
+}
+
+/// How multiple tags are combined in `AssetStore::find_by_tags`.
+#[derive(Eq, PartialEq, Debug, Copy, Clone)]
+pub enum TagMatch {
+    /// Assets need to have every tag.
+    All,
+    /// Assets need to have at least one of the tags.
+    Any,
+}
+
+/// Tags are case-insensitive and surrounding whitespace is ignored.
+fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+impl Default for AssetStore {
+    fn default() -> AssetStore {
+        AssetStore::new()
+    }
+}
+
+impl MutableStore for AssetStore {
+    /// Will return an error when the asset has a file more than once,
+    /// or when its title is not valid, see `validate_title`.
+    fn insert(&mut self, mut asset: Asset) -> Result<AssetId> {
+        asset.title = validate_title(&asset.title)?.to_string();
+        for (i, file) in asset.files.iter().enumerate() {
+            if asset.files[..i].contains(file) {
+                bail!("File {:?} is given more than once.", file);
+            }
+        }
+
+        let id = loop {
+            let id = AssetId(self.allocator.allocate().context("Ran out of asset ids.")?);
+            if !self.assets.contains_key(&id) {
+                break id;
+            }
+        };
+        asset.id = id;
+
+        self.assets.insert(id, asset);
+
+        Ok(id)
+    }
+}
+
+/// Consumes the store, yielding the assets by value, in no particular order.
+impl IntoIterator for AssetStore {
+    type Item = (AssetId, Asset);
+    type IntoIter = std::collections::hash_map::IntoIter<AssetId, Asset>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.assets.into_iter()
+    }
+}
+
+impl IndexedStore for AssetStore {
+    type Id = AssetId;
+    type Item = Asset;
+
+    fn get(&self, id: AssetId) -> Option<&Asset> {
+        self.assets.get(&id)
+    }
+
+    fn get_mut(&mut self, id: AssetId) -> Option<&mut Asset> {
+        self.assets.get_mut(&id)
+    }
+
+    fn count(&self) -> usize {
+        self.assets.len()
+    }
+
+    fn item_kind(&self) -> &'static str {
+        "asset"
+    }
+
+    fn contains(&self, id: AssetId) -> bool {
+        self.assets.contains_key(&id)
+    }
+
+    fn remove(&mut self, id: &Self::Id) -> Option<Self::Item> {
+        self.assets.remove(id)
+    }
+
+    fn clear(&mut self) {
+        self.assets.clear();
+    }
+
+    fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&AssetId, &Asset) -> bool,
+    {
+        self.assets.retain(|id, asset| keep(id, asset));
+    }
+
+    fn iter(&self) -> StoreIter<'_, Self::Id, Self::Item> {
+        Box::new(self.assets.iter())
+    }
+}
+
+/// Something the user wants to keep track of, backed by files in the `FileStore`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Asset {
+    id: AssetId,
+    title: String,
+    /// In the order they were added, the first one is the primary file.
+    files: Vec<FileId>,
+    /// Tags the user has given this asset, always normalized.
+    tags: HashSet<String>,
+    /// Free text about the asset, empty when there is none.
+    #[cfg_attr(feature = "serde", serde(default))]
+    description: String,
+    /// Hidden from listings, see `AssetStore::set_archived`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    archived: bool,
+    /// When the asset was added, or when its title, description, tags, files or archived state
+    /// last changed.
+    #[cfg_attr(feature = "serde", serde(default = "unknown_time"))]
+    modified_at: SystemTime,
+}
+
+impl Asset {
+    pub fn title(&self) -> &str {
+        self.title.as_str()
+    }
+
+    pub fn files(&self) -> &[FileId] {
+        &self.files
+    }
+
+    /// The first of the asset's files.
+    pub fn primary_file(&self) -> Option<FileId> {
+        self.files.first().copied()
+    }
+
+    pub fn tags(&self) -> &HashSet<String> {
+        &self.tags
+    }
+
+    pub fn description(&self) -> &str {
+        self.description.as_str()
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.archived
+    }
+
+    /// When the asset was added, or when its title, description, tags, files or archived state
+    /// last changed.
+    pub fn modified_at(&self) -> SystemTime {
+        self.modified_at
+    }
+}
+
+#[cfg(test)]
+mod test_asset_store {
+    use super::*;
+
+    /// The asset store never looks up its files, so any ids will do.
+    fn file_ids<const N: usize>() -> [FileId; N] {
+        std::array::from_fn(|i| FileId::from_u32(i as u32))
+    }
+
+    /// When inserting new assets, the generated ids must be different.
+    #[test]
+    fn new_assets_should_have_different_ids() {
+        let [file] = file_ids();
+        let mut store = AssetStore::new();
+
+        let id_1 = store.new_asset("test asset", &[file]).unwrap();
+        let id_2 = store.new_asset("SDKDKK@K@@", &[file]).unwrap();
+        let id_3 = store.new_asset("test {}", &[file]).unwrap();
+
+        assert_ne!(id_1, id_2, "Assigned ids must be unique.");
+        assert_ne!(id_2, id_3, "Assigned ids must be unique.");
+        assert_ne!(id_3, id_1, "Assigned ids must be unique.");
+        assert_eq!(store.count(), 3);
+    }
+
+    /// Once the ids run out, new assets should be refused instead of reusing ids.
+    #[test]
+    fn running_out_of_ids_fails() {
+        let [file] = file_ids();
+        let mut store = AssetStore::with_next_id(u32::MAX - 1);
+
+        let id = store.new_asset("last", &[file]).unwrap();
+        assert_eq!(id, AssetId(u32::MAX - 1));
+        assert!(store.new_asset("one too many", &[file]).is_err());
+        assert_eq!(store.count(), 1);
+    }
+
+    /// Hands out ids counting down from `next`.
+    struct CountDown {
+        next: u32,
+    }
+
+    impl IdAllocator for CountDown {
+        fn allocate(&mut self) -> Option<u32> {
+            let id = self.next;
+            self.next = id.checked_sub(1)?;
+            Some(id)
+        }
+
+        fn mark_used(&mut self, _id: u32) {}
+
+        fn next_id(&self) -> u32 {
+            self.next
+        }
+    }
+
+    #[test]
+    fn custom_allocator_decides_the_ids() {
+        let mut store = AssetStore::with_allocator(Box::new(CountDown { next: 1000 }));
+
+        assert_eq!(store.new_asset("first", &[]).unwrap(), AssetId(1000));
+        assert_eq!(store.new_asset("second", &[]).unwrap(), AssetId(999));
+
+        // Both ids are taken, so they are skipped.
+        store.set_allocator(Box::new(SequentialAllocator::starting_at(999)));
+        assert_eq!(store.new_asset("third", &[]).unwrap(), AssetId(1001));
+    }
+
+    #[test]
+    fn getting_and_removing_assets() {
+        let [file] = file_ids();
+        let mut store = AssetStore::new();
+
+        let id = store.new_asset("!@@#$@#@", &[file]).unwrap();
+        let asset = store.get(id).unwrap();
+        assert_eq!(asset.title(), "!@@#$@#@");
+        assert_eq!(asset.primary_file(), Some(file));
+
+        assert!(store.remove(&id).is_some());
+        assert_eq!(store.count(), 0);
+        assert!(store.get(id).is_none());
+    }
+
+    #[test]
+    fn contains_and_is_empty() {
+        let [file] = file_ids();
+        let mut store = AssetStore::new();
+        assert!(store.is_empty());
+        assert!(!store.contains(AssetId(0)));
+
+        let id = store.new_asset("asset", &[file]).unwrap();
+        assert!(!store.is_empty());
+        assert!(store.contains(id));
+        assert!(!store.contains(AssetId(10)));
+    }
+
+    #[test]
+    fn changes_through_get_mut_are_visible() {
+        let [file] = file_ids();
+        let mut store = AssetStore::new();
+        let id = store.new_asset("old title", &[file]).unwrap();
+
+        store.get_mut(id).unwrap().title = "new title".to_string();
+        assert_eq!(store.get(id).unwrap().title(), "new title");
+
+        assert!(store.get_mut(AssetId(10)).is_none());
+    }
+
+    #[test]
+    fn assets_with_multiple_files() {
+        let [file_1, file_2, file_3] = file_ids();
+        let mut store = AssetStore::new();
+
+        let id = store.new_asset("icon", &[file_2, file_1]).unwrap();
+        assert_eq!(store.get(id).unwrap().files(), &[file_2, file_1]);
+        assert_eq!(store.get(id).unwrap().primary_file(), Some(file_2));
+
+        assert!(store.add_file_to_asset(id, file_3));
+        assert_eq!(store.get(id).unwrap().files(), &[file_2, file_1, file_3]);
+
+        assert!(store.remove_file_from_asset(id, file_2));
+        assert!(!store.remove_file_from_asset(id, file_2));
+        assert_eq!(store.get(id).unwrap().files(), &[file_1, file_3]);
+        assert_eq!(store.get(id).unwrap().primary_file(), Some(file_1));
+    }
+
+    #[test]
+    fn duplicate_files_are_rejected() {
+        let [file_1, file_2] = file_ids();
+        let mut store = AssetStore::new();
+
+        assert!(store.new_asset("twice", &[file_1, file_2, file_1]).is_err());
+        assert!(store.is_empty());
+
+        let id = store.new_asset("once", &[file_1]).unwrap();
+        assert!(!store.add_file_to_asset(id, file_1));
+        assert_eq!(store.get(id).unwrap().files(), &[file_1]);
+        assert!(!store.add_file_to_asset(AssetId(10), file_2));
+    }
+
+    #[test]
+    fn set_title_keeps_id_and_file() {
+        let [file] = file_ids();
+        let mut store = AssetStore::new();
+        let id = store.new_asset("old title", &[file]).unwrap();
+
+        assert!(store.set_title(id, "  New Title\t"));
+
+        let asset = store.get(id).unwrap();
+        assert_eq!(asset.title(), "New Title");
+        assert_eq!(asset.id, id);
+        assert_eq!(asset.primary_file(), Some(file));
+
+        assert!(!store.set_title(AssetId(10), "nope"));
+        assert!(!store.set_title(id, " "));
+        assert!(!store.set_title(id, "New\rTitle"));
+        assert_eq!(store.get(id).unwrap().title(), "New Title");
+        assert!(store.new_asset("Two\nlines", &[file]).is_err());
+    }
+
+    #[test]
+    fn assets_using_file_finds_all_users() {
+        let [shared, unused] = file_ids();
+        let mut store = AssetStore::new();
+        let id_1 = store.new_asset("first", &[shared]).unwrap();
+        let id_2 = store.new_asset("second", &[shared]).unwrap();
+
+        let users = store.assets_using_file(shared);
+        assert_eq!(users.len(), 2);
+        assert!(users.contains(&id_1));
+        assert!(users.contains(&id_2));
+        assert!(store.assets_using_file(unused).is_empty());
+
+        let used = store.used_files();
+        assert!(used.contains(&shared));
+        assert!(!used.contains(&unused));
+    }
+
+    #[test]
+    fn default_is_empty() {
+        let store: AssetStore = Default::default();
+        assert_eq!(store.count(), 0);
+    }
+
+    #[test]
+    fn insert_through_a_trait_object() {
+        let [file] = file_ids();
+        let mut source = AssetStore::new();
+        source.new_asset("first", &[file]).unwrap();
+        source.new_asset("second", &[]).unwrap();
+
+        let mut dest = AssetStore::with_next_id(5);
+        let store: &mut dyn MutableStore<Id = AssetId, Item = Asset> = &mut dest;
+        let mut ids: Vec<AssetId> = source
+            .into_iter()
+            .map(|(_, asset)| store.insert(asset).unwrap())
+            .collect();
+        ids.sort();
+
+        assert_eq!(ids, vec![AssetId(5), AssetId(6)]);
+        assert_eq!(store.count(), 2);
+        assert_eq!(dest.assets_using_file(file).len(), 1);
+    }
+
+    #[test]
+    fn with_capacity_behaves_like_new() {
+        let [file] = file_ids();
+        let mut store = AssetStore::with_capacity(50);
+        assert!(store.assets.capacity() >= 50);
+        assert!(store.is_empty());
+
+        for i in 0..50 {
+            let id = store.new_asset(&i.to_string(), &[file]).unwrap();
+            assert_eq!(id, AssetId(i));
+        }
+        assert_eq!(store.count(), 50);
+        assert_eq!(store.assets_using_file(file).len(), 50);
+    }
+
+    #[test]
+    fn into_iter_yields_every_asset_once() {
+        let [file] = file_ids();
+        let mut store = AssetStore::new();
+        let id_1 = store.new_asset("first", &[file]).unwrap();
+        let id_2 = store.new_asset("second", &[file]).unwrap();
+
+        let assets: Vec<(AssetId, Asset)> = store.into_iter().collect();
+        assert_eq!(assets.len(), 2);
+        for (id, title) in [(id_1, "first"), (id_2, "second")] {
+            let matching: Vec<_> = assets
+                .iter()
+                .filter(|(asset_id, _)| *asset_id == id)
+                .collect();
+            assert_eq!(matching.len(), 1);
+            assert_eq!(matching[0].1.title(), title);
+        }
+    }
+
+    #[test]
+    fn ids_and_values_yield_everything() {
+        let [file] = file_ids();
+        let mut store = AssetStore::new();
+        let id_1 = store.new_asset("first", &[file]).unwrap();
+        let id_2 = store.new_asset("second", &[file]).unwrap();
+
+        let ids: Vec<AssetId> = store.ids().copied().collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&id_1));
+        assert!(ids.contains(&id_2));
+
+        let mut titles: Vec<&str> = store.values().map(|asset| asset.title()).collect();
+        titles.sort();
+        assert_eq!(titles, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn placeholder_assets_get_their_file_later() {
+        let [file_1, file_2] = file_ids();
+        let mut store = AssetStore::new();
+        let id = store.new_asset("placeholder", &[]).unwrap();
+        assert_eq!(store.get(id).unwrap().primary_file(), None);
+
+        assert!(store.set_primary_file(id, file_1));
+        assert_eq!(store.get(id).unwrap().primary_file(), Some(file_1));
+
+        assert!(store.set_primary_file(id, file_2));
+        assert!(store.set_primary_file(id, file_1));
+        assert_eq!(store.get(id).unwrap().files(), &[file_1, file_2]);
+
+        assert!(!store.set_primary_file(AssetId(9), file_1));
+    }
+
+    #[test]
+    fn set_file_replaces_the_primary_file() {
+        let [old, other, new] = file_ids();
+        let mut store = AssetStore::new();
+        let id = store.new_asset("asset", &[old, other]).unwrap();
+
+        assert!(store.set_file(id, new));
+        assert_eq!(store.get(id).unwrap().files(), &[new, other]);
+
+        // A file the asset already has is not added twice.
+        assert!(store.set_file(id, other));
+        assert_eq!(store.get(id).unwrap().files(), &[other]);
+
+        let empty = store.new_asset("empty", &[]).unwrap();
+        assert!(store.set_file(empty, new));
+        assert_eq!(store.get(empty).unwrap().files(), &[new]);
+
+        assert!(!store.set_file(AssetId(9), new));
+    }
+
+    #[test]
+    fn set_file_drops_the_old_primary_and_set_primary_file_keeps_it() {
+        let [first, second, third, fourth] = file_ids();
+        let mut store = AssetStore::new();
+        let id = store.new_asset("asset", &[first, second, third]).unwrap();
+
+        assert!(store.set_primary_file(id, third));
+        assert_eq!(store.get(id).unwrap().files(), &[third, first, second]);
+
+        assert!(store.set_file(id, second));
+        assert_eq!(store.get(id).unwrap().files(), &[second, first]);
+
+        assert!(store.set_primary_file(id, fourth));
+        assert_eq!(store.get(id).unwrap().files(), &[fourth, second, first]);
+
+        assert!(store.set_file(id, third));
+        assert_eq!(store.get(id).unwrap().files(), &[third, second, first]);
+    }
+
+    #[test]
+    fn set_file_with_a_file_that_is_already_attached() {
+        let [primary, second, third] = file_ids();
+        let mut store = AssetStore::new();
+        let id = store.new_asset("asset", &[primary, second, third]).unwrap();
+
+        assert!(store.set_file(id, third));
+        assert_eq!(store.get(id).unwrap().files(), &[third, second]);
+
+        // Already the primary file, so nothing changes.
+        let modified_at = store.get(id).unwrap().modified_at();
+        assert!(store.set_file(id, third));
+        assert_eq!(store.get(id).unwrap().files(), &[third, second]);
+        assert_eq!(store.get(id).unwrap().modified_at(), modified_at);
+    }
+
+    #[test]
+    fn changes_update_modified_at() {
+        let mut store = AssetStore::new();
+        let id = store.new_asset("asset", &[]).unwrap();
+        let added_at = store.get(id).unwrap().modified_at();
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        store.add_tag(id, "sword");
+        let tagged_at = store.get(id).unwrap().modified_at();
+        assert!(tagged_at > added_at);
+
+        // Nothing to add or remove, so nothing changes.
+        store.add_tag(id, "Sword");
+        store.remove_tag(id, "shield");
+        assert_eq!(store.get(id).unwrap().modified_at(), tagged_at);
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        store.set_title(id, "new title");
+        assert!(store.get(id).unwrap().modified_at() > tagged_at);
+    }
+
+    #[test]
+    fn archive_and_restore() {
+        let mut store = AssetStore::new();
+        let id = store.new_asset("asset", &[]).unwrap();
+        assert!(!store.get(id).unwrap().is_archived());
+
+        assert!(store.set_archived(id, true));
+        assert!(store.get(id).unwrap().is_archived());
+        assert!(store.set_archived(id, false));
+        assert!(!store.get(id).unwrap().is_archived());
+
+        assert!(!store.set_archived(AssetId(9), true));
+    }
+
+    #[test]
+    fn retain_by_title() {
+        let mut store = AssetStore::new();
+        let kept = store.new_asset("sword", &[]).unwrap();
+        store.new_asset("draft sword", &[]).unwrap();
+
+        store.retain(|_, asset| !asset.title().starts_with("draft"));
+
+        assert_eq!(store.count(), 1);
+        assert!(store.contains(kept));
+    }
+
+    #[test]
+    fn clear_does_not_reuse_ids() {
+        let mut store = AssetStore::new();
+        let id_1 = store.new_asset("first", &[]).unwrap();
+
+        store.clear();
+        assert!(store.is_empty());
+
+        let id_2 = store.new_asset("second", &[]).unwrap();
+        assert_ne!(id_1, id_2);
+    }
+
+    #[test]
+    fn ids_round_trip_through_strings() {
+        let id = AssetId::from_u32(42);
+        assert_eq!(id.as_u32(), 42);
+        assert_eq!(id.to_string(), "42");
+        assert_eq!("42".parse::<AssetId>().unwrap(), id);
+
+        assert!("sword".parse::<AssetId>().is_err());
+        assert!("-1".parse::<AssetId>().is_err());
+    }
+
+    #[test]
+    fn set_and_clear_description() {
+        let mut store = AssetStore::new();
+        let id = store.new_asset("asset", &[]).unwrap();
+        assert_eq!(store.get(id).unwrap().description(), "");
+
+        assert!(store.set_description(id, "A sword.\nVery sharp."));
+        assert_eq!(
+            store.get(id).unwrap().description(),
+            "A sword.\nVery sharp."
+        );
+
+        assert!(store.set_description(id, ""));
+        assert_eq!(store.get(id).unwrap().description(), "");
+        assert!(!store.set_description(AssetId(4), "unknown"));
+    }
+
+    #[test]
+    fn find_by_multiple_tags() {
+        let mut store = AssetStore::new();
+        let sword = store.new_asset("sword", &[]).unwrap();
+        let axe = store.new_asset("axe", &[]).unwrap();
+        let shield = store.new_asset("shield", &[]).unwrap();
+        for tag in &["weapon", "metal"] {
+            store.add_tag(sword, tag);
+        }
+        store.add_tag(axe, "weapon");
+        store.add_tag(shield, "wood");
+
+        assert_eq!(
+            store.find_by_tags(&["Weapon ", "METAL"], TagMatch::All),
+            vec![sword]
+        );
+        assert_eq!(
+            store.find_by_tags(&["weapon", "metal"], TagMatch::Any),
+            vec![sword, axe]
+        );
+        assert_eq!(
+            store.find_by_tags(&["metal", "wood"], TagMatch::Any),
+            vec![sword, shield]
+        );
+        assert!(store
+            .find_by_tags(&["metal", "wood"], TagMatch::All)
+            .is_empty());
+    }
+
+    #[test]
+    fn find_by_no_tags() {
+        let mut store = AssetStore::new();
+        let id_1 = store.new_asset("first", &[]).unwrap();
+        let id_2 = store.new_asset("second", &[]).unwrap();
+        store.add_tag(id_1, "tag");
+
+        assert_eq!(store.find_by_tags(&[], TagMatch::All), vec![id_1, id_2]);
+        assert!(store.find_by_tags(&[], TagMatch::Any).is_empty());
+    }
+
+    #[test]
+    fn tags_are_normalized() {
+        let [file] = file_ids();
+        let mut store = AssetStore::new();
+        let id = store.new_asset("asset", &[file]).unwrap();
+
+        assert!(store.add_tag(id, "Sword"));
+        assert!(!store.add_tag(id, "  sword "));
+        assert!(!store.add_tag(id, "SWORD"));
+
+        let tags = store.get(id).unwrap().tags();
+        assert_eq!(tags.len(), 1);
+        assert!(tags.contains("sword"));
+
+        // Empty tags and unknown assets are ignored.
+        assert!(!store.add_tag(id, "   "));
+        assert!(!store.add_tag(AssetId(10), "sword"));
+    }
+
+    #[test]
+    fn find_by_tag_follows_adding_and_removing() {
+        let [file] = file_ids();
+        let mut store = AssetStore::new();
+        let id_1 = store.new_asset("first", &[file]).unwrap();
+        let id_2 = store.new_asset("second", &[file]).unwrap();
+
+        store.add_tag(id_1, "weapon");
+        store.add_tag(id_2, "Weapon");
+        store.add_tag(id_2, "blue");
+
+        let found = store.find_by_tag("WEAPON");
+        assert_eq!(found.len(), 2);
+        assert!(found.contains(&id_1));
+        assert!(found.contains(&id_2));
+        assert_eq!(store.find_by_tag("blue"), vec![id_2]);
+
+        assert!(store.remove_tag(id_2, " Weapon"));
+        assert_eq!(store.find_by_tag("weapon"), vec![id_1]);
+        assert!(!store.remove_tag(id_2, "weapon"));
+    }
+
+    #[test]
+    fn tag_many_assets_at_once() {
+        let mut store = AssetStore::new();
+        let id_1 = store.new_asset("first", &[]).unwrap();
+        let id_2 = store.new_asset("second", &[]).unwrap();
+        let untouched = store.new_asset("third", &[]).unwrap();
+        store.add_tag(id_2, "weapon");
+        let unknown = AssetId(10);
+
+        assert_eq!(store.add_tag_to_many(&[id_1, unknown, id_2], " Weapon"), 1);
+        let mut tagged = store.find_by_tag("weapon");
+        tagged.sort();
+        assert_eq!(tagged, vec![id_1, id_2]);
+        assert!(store.get(untouched).unwrap().tags().is_empty());
+        assert_eq!(store.add_tag_to_many(&[id_1, id_2], "  "), 0);
+
+        assert_eq!(
+            store.remove_tag_from_many(&[id_1, unknown, untouched, id_2], "WEAPON"),
+            2
+        );
+        assert!(store.find_by_tag("weapon").is_empty());
+        assert_eq!(store.remove_tag_from_many(&[id_1], "weapon"), 0);
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test_asset_store_serde {
+    use super::*;
+
+    #[test]
+    fn description_survives_json_round_trip() {
+        let mut store = AssetStore::new();
+        let id = store.new_asset("asset", &[]).unwrap();
+        store.set_description(id, "Some \"quoted\" text");
+
+        let json = serde_json::to_string(&store).unwrap();
+        let loaded: AssetStore = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            loaded.get(id).unwrap().description(),
+            "Some \"quoted\" text"
+        );
+    }
+
+    #[test]
+    fn archived_survives_json_round_trip() {
+        let mut store = AssetStore::new();
+        let archived = store.new_asset("archived", &[]).unwrap();
+        let active = store.new_asset("active", &[]).unwrap();
+        store.set_archived(archived, true);
+
+        let json = serde_json::to_string(&store).unwrap();
+        let loaded: AssetStore = serde_json::from_str(&json).unwrap();
+
+        assert!(loaded.get(archived).unwrap().is_archived());
+        assert!(!loaded.get(active).unwrap().is_archived());
+    }
+
+    /// Indexes from before descriptions existed can still be loaded.
+    #[test]
+    fn missing_description_is_empty() {
+        let json = r#"{"assets":{"0":{"id":0,"title":"old","files":[],"tags":[]}},"next_id":1}"#;
+        let loaded: AssetStore = serde_json::from_str(json).unwrap();
+
+        assert_eq!(loaded.get(AssetId(0)).unwrap().description(), "");
+        assert!(!loaded.get(AssetId(0)).unwrap().is_archived());
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use super::asset_store::AssetId;
+use super::id_allocator::{IdAllocator, SequentialAllocator};
+use super::traits::IndexedStore;
+use crate::stores::traits::{MutableStore, StoreId, StoreIter};
+use anyhow::{bail, Context, Result};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Handed out by a `CollectionStore` when a new collection is added.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct CollectionId(u32);
+
+impl StoreId for CollectionId {
+    fn from_u32(id: u32) -> Self {
+        CollectionId(id)
+    }
+
+    fn as_u32(&self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for CollectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for CollectionId {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(CollectionId)
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CollectionStore {
+    collections: HashMap<CollectionId, Collection>,
+    #[cfg_attr(feature = "serde", serde(rename = "next_id"))]
+    allocator: Box<dyn IdAllocator>,
+}
+
+impl CollectionStore {
+    pub fn new() -> CollectionStore {
+        CollectionStore::with_capacity(0)
+    }
+
+    /// Store with room for `capacity` collections before it has to grow.
+    pub fn with_capacity(capacity: usize) -> CollectionStore {
+        CollectionStore {
+            collections: HashMap::with_capacity(capacity),
+            allocator: Box::new(SequentialAllocator::new()),
+        }
+    }
+
+    /// Creates a new, empty collection. Surrounding whitespace is removed from the name.
+    /// Will return an error when there are no more ids left to hand out.
+    pub fn new_collection(&mut self, name: &str) -> Result<CollectionId> {
+        self.insert(Collection {
+            // Replaced by the id that `insert` hands out.
+            id: CollectionId(0),
+            name: name.trim().to_string(),
+            members: Vec::new(),
+        })
+    }
+
+    /// Changes the name of a collection. Surrounding whitespace is removed.
+    /// Returns whether the collection exists.
+    pub fn set_name(&mut self, id: CollectionId, name: &str) -> bool {
+        match self.collections.get_mut(&id) {
+            Some(collection) => {
+                collection.name = name.trim().to_string();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds an asset to the end of a collection.
+    /// Does not check whether the asset actually exists, that is up to the caller.
+    /// Returns false if the collection does not exist, or if it already has the asset.
+    pub fn add_member(&mut self, id: CollectionId, asset: AssetId) -> bool {
+        match self.collections.get_mut(&id) {
+            Some(collection) if !collection.members.contains(&asset) => {
+                collection.members.push(asset);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Removes an asset from a collection, keeping the order of the other assets.
+    /// Returns whether the collection had the asset.
+    pub fn remove_member(&mut self, id: CollectionId, asset: AssetId) -> bool {
+        match self.collections.get_mut(&id) {
+            Some(collection) => {
+                let count = collection.members.len();
+                collection.members.retain(|&a| a != asset);
+                collection.members.len() != count
+            }
+            None => false,
+        }
+    }
+
+    /// Removes an asset from every collection it is in, like when the asset is removed.
+    pub fn remove_from_all(&mut self, asset: AssetId) {
+        for collection in self.collections.values_mut() {
+            collection.members.retain(|&a| a != asset);
+        }
+    }
+
+    /// Returns all the collections that have the given asset. The ids are sorted.
+    pub fn collections_with(&self, asset: AssetId) -> Vec<CollectionId> {
+        let mut found: Vec<CollectionId> = self
+            .collections
+            .values()
+            .filter(|collection| collection.members.contains(&asset))
+            .map(|collection| collection.id)
+            .collect();
+
+        found.sort();
+        found
+    }
+}
+
+impl Default for CollectionStore {
+    fn default() -> CollectionStore {
+        CollectionStore::new()
+    }
+}
+
+impl MutableStore for CollectionStore {
+    /// Will return an error when the collection has an asset more than once.
+    fn insert(&mut self, mut collection: Collection) -> Result<CollectionId> {
+        for (i, asset) in collection.members.iter().enumerate() {
+            if collection.members[..i].contains(asset) {
+                bail!("Asset {:?} is given more than once.", asset);
+            }
+        }
+
+        let id = loop {
+            let id = CollectionId(
+                self.allocator
+                    .allocate()
+                    .context("Ran out of collection ids.")?,
+            );
+            if !self.collections.contains_key(&id) {
+                break id;
+            }
+        };
+        collection.id = id;
+
+        self.collections.insert(id, collection);
+
+        Ok(id)
+    }
+}
+
+/// Consumes the store, yielding the collections by value, in no particular order.
+impl IntoIterator for CollectionStore {
+    type Item = (CollectionId, Collection);
+    type IntoIter = std::collections::hash_map::IntoIter<CollectionId, Collection>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.collections.into_iter()
+    }
+}
+
+impl IndexedStore for CollectionStore {
+    type Id = CollectionId;
+    type Item = Collection;
+
+    fn get(&self, id: CollectionId) -> Option<&Collection> {
+        self.collections.get(&id)
+    }
+
+    fn get_mut(&mut self, id: CollectionId) -> Option<&mut Collection> {
+        self.collections.get_mut(&id)
+    }
+
+    fn count(&self) -> usize {
+        self.collections.len()
+    }
+
+    fn item_kind(&self) -> &'static str {
+        "collection"
+    }
+
+    fn contains(&self, id: CollectionId) -> bool {
+        self.collections.contains_key(&id)
+    }
+
+    fn remove(&mut self, id: &Self::Id) -> Option<Self::Item> {
+        self.collections.remove(id)
+    }
+
+    fn clear(&mut self) {
+        self.collections.clear();
+    }
+
+    fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&CollectionId, &Collection) -> bool,
+    {
+        self.collections
+            .retain(|id, collection| keep(id, collection));
+    }
+
+    fn iter(&self) -> StoreIter<'_, Self::Id, Self::Item> {
+        Box::new(self.collections.iter())
+    }
+}
+
+/// A named group of assets, like a folder or an album.
+/// An asset can be in any number of collections.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Collection {
+    id: CollectionId,
+    name: String,
+    /// In the order they were added.
+    members: Vec<AssetId>,
+}
+
+impl Collection {
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn members(&self) -> &[AssetId] {
+        &self.members
+    }
+
+    pub fn contains(&self, asset: AssetId) -> bool {
+        self.members.contains(&asset)
+    }
+}
+
+#[cfg(test)]
+mod test_collection_store {
+    use super::*;
+
+    #[test]
+    fn add_and_remove_members() {
+        let mut store = CollectionStore::new();
+        let id = store.new_collection(" Weapons ").unwrap();
+        let sword = AssetId::from_u32(0);
+        let shield = AssetId::from_u32(1);
+
+        assert_eq!(store.get(id).unwrap().name(), "Weapons");
+        assert!(store.add_member(id, sword));
+        assert!(store.add_member(id, shield));
+        assert!(!store.add_member(id, sword));
+        assert_eq!(store.get(id).unwrap().members(), &[sword, shield]);
+
+        assert!(store.remove_member(id, sword));
+        assert!(!store.remove_member(id, sword));
+        assert!(!store.get(id).unwrap().contains(sword));
+        assert!(store.get(id).unwrap().contains(shield));
+
+        let unknown = CollectionId(9);
+        assert!(!store.add_member(unknown, sword));
+        assert!(!store.remove_member(unknown, sword));
+    }
+
+    #[test]
+    fn find_collections_with_an_asset() {
+        let mut store = CollectionStore::new();
+        let weapons = store.new_collection("Weapons").unwrap();
+        let favorites = store.new_collection("Favorites").unwrap();
+        let empty = store.new_collection("Empty").unwrap();
+        let sword = AssetId::from_u32(0);
+        store.add_member(weapons, sword);
+        store.add_member(favorites, sword);
+
+        assert_eq!(store.collections_with(sword), vec![weapons, favorites]);
+        assert!(store.collections_with(AssetId::from_u32(1)).is_empty());
+
+        store.remove_from_all(sword);
+        assert!(store.collections_with(sword).is_empty());
+        assert!(store.get(empty).unwrap().members().is_empty());
+    }
+
+    #[test]
+    fn renaming_collections() {
+        let mut store = CollectionStore::new();
+        let id = store.new_collection("Old").unwrap();
+
+        assert!(store.set_name(id, " New "));
+        assert_eq!(store.get(id).unwrap().name(), "New");
+        assert!(!store.set_name(CollectionId(9), "New"));
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test_collection_store_serde {
+    use super::*;
+
+    #[test]
+    fn collections_survive_json_round_trip() {
+        let mut store = CollectionStore::new();
+        let id = store.new_collection("Weapons").unwrap();
+        store.add_member(id, AssetId::from_u32(3));
+
+        let json = serde_json::to_string(&store).unwrap();
+        let mut loaded: CollectionStore = serde_json::from_str(&json).unwrap();
+
+        let collection = loaded.get(id).unwrap();
+        assert_eq!(collection.name(), "Weapons");
+        assert_eq!(collection.members(), &[AssetId::from_u32(3)]);
+        assert_ne!(loaded.new_collection("Other").unwrap(), id);
+    }
+}
@@ -1,55 +1,588 @@
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
 
+use super::id_allocator::{IdAllocator, SequentialAllocator};
 use super::traits::IndexedStore;
-use crate::stores::traits::StoreId;
-use std::collections::hash_map::Iter;
+use crate::content_hash;
+use crate::stores::traits::{MutableStore, StoreId, StoreIter};
+use anyhow::{bail, Context, Result};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// Handed out by a `FileStore` when a new file is added.
-#[derive(Eq, PartialEq, Hash, Debug, Copy, Clone)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct FileId(u32);
 
-impl FileId {
-    fn to_string(&self) -> String {
-        self.0.to_string()
+impl fmt::Display for FileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl StoreId for FileId {
+    fn from_u32(id: u32) -> Self {
+        FileId(id)
+    }
+
+    fn as_u32(&self) -> u32 {
+        self.0
     }
 }
 
-impl StoreId for FileId {}
+impl FromStr for FileId {
+    type Err = ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(FileId)
+    }
+}
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "SerializedFileStore"))]
 pub struct FileStore {
     files: HashMap<FileId, File>,
-    next_id: FileId,
+    #[cfg_attr(feature = "serde", serde(rename = "next_id"))]
+    allocator: Box<dyn IdAllocator>,
+    /// Normalized titles to the files that have them, see `FileStore::exact_title_lookup`.
+    /// Can be rebuilt from the files, so it is not serialized.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    title_index: HashMap<String, HashSet<FileId>>,
+}
+
+/// The serialized part of a `FileStore`, the title index is rebuilt when deserializing.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct SerializedFileStore {
+    files: HashMap<FileId, File>,
+    next_id: Box<dyn IdAllocator>,
+}
+
+#[cfg(feature = "serde")]
+impl From<SerializedFileStore> for FileStore {
+    fn from(stored: SerializedFileStore) -> FileStore {
+        let mut store = FileStore {
+            files: stored.files,
+            allocator: stored.next_id,
+            title_index: HashMap::new(),
+        };
+        let titles: Vec<(FileId, String)> = store
+            .files
+            .values()
+            .map(|file| (file.id, file.title.clone()))
+            .collect();
+        for (id, title) in titles {
+            store.index_title(id, &title);
+        }
+        store
+    }
+}
+
+/// Checks that a title can be shown and exported: it can't be empty after removing the
+/// surrounding whitespace, and it can't have control characters like newlines or tabs.
+/// Any other unicode is fine. Returns the title without the surrounding whitespace.
+pub fn validate_title(title: &str) -> Result<&str> {
+    let title = title.trim();
+    if title.is_empty() {
+        bail!("Titles can not be empty.");
+    }
+    if title.chars().any(char::is_control) {
+        bail!("Title {:?} contains control characters.", title);
+    }
+    Ok(title)
+}
+
+/// Key for the title index: titles are compared without surrounding whitespace and case.
+fn normalize_title(title: &str) -> String {
+    title.trim().to_lowercase()
 }
 
 impl FileStore {
     pub fn new() -> FileStore {
+        FileStore::with_capacity(0)
+    }
+
+    /// Store with room for `capacity` files before it has to grow, for bulk imports.
+    pub fn with_capacity(capacity: usize) -> FileStore {
+        FileStore {
+            files: HashMap::with_capacity(capacity),
+            allocator: Box::new(SequentialAllocator::new()),
+            title_index: HashMap::new(),
+        }
+    }
+
+    /// Store that hands out its ids with the given allocator, instead of counting up from 0.
+    pub fn with_allocator(allocator: Box<dyn IdAllocator>) -> FileStore {
         FileStore {
             files: HashMap::new(),
-            next_id: FileId(0),
+            allocator,
+            title_index: HashMap::new(),
+        }
+    }
+
+    /// Replaces how ids are handed out from now on, like for a store that was just loaded.
+    /// Ids that are already in use are skipped, whatever the allocator says.
+    pub fn set_allocator(&mut self, allocator: Box<dyn IdAllocator>) {
+        self.allocator = allocator;
+    }
+
+    /// Store that starts handing out ids at `next_id`.
+    #[cfg(test)]
+    fn with_next_id(next_id: u32) -> FileStore {
+        FileStore::with_allocator(Box::new(SequentialAllocator::starting_at(next_id)))
+    }
+
+    /// Rebuilds a store from the files in the files directory, for when the index is lost.
+    /// Files that are not named `<id>.<extension>` are skipped, like `03.png`.
+    /// The titles are not stored on disk, so the id is used as title.
+    /// Will return an error if two files have the same id, like `3.png` and `3.jpg`,
+    /// because there is no telling which one is the right one.
+    pub fn from_directory(files_dir: &Path) -> Result<FileStore> {
+        let mut store = FileStore::new();
+
+        let entries = std::fs::read_dir(files_dir).with_context(|| {
+            format!("Could not read files directory \"{}\"", files_dir.display())
+        })?;
+        for entry in entries {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+
+            let extension = match KnownExtension::from_path(&path) {
+                Some(extension) => extension,
+                None => continue,
+            };
+            let stem = path.file_stem().unwrap_or_default().to_str().unwrap_or("");
+            let id = match stem.parse::<FileId>() {
+                // Stored files are always named after the id exactly, so "03" or "+3" are not ours.
+                Ok(id) if id.to_string() == stem => id,
+                _ => continue,
+            };
+            if let Some(existing) = store.files.get(&id) {
+                bail!(
+                    "Files \"{}\" and \"{}\" in \"{}\" both have id {}",
+                    existing.file_name().display(),
+                    path.file_name().unwrap_or_default().to_string_lossy(),
+                    files_dir.display(),
+                    id
+                );
+            }
+
+            let metadata = std::fs::metadata(&path)?;
+            let file = File {
+                id,
+                title: stem.to_string(),
+                extension,
+                system_tags: HashSet::new(),
+                content_hash: Some(content_hash::hash_file(&path)?),
+                dimensions: None,
+                perceptual_hash: None,
+                size_bytes: metadata.len(),
+                // When it was added is lost, so when it was last written is the best we have.
+                created_at: metadata.modified().unwrap_or_else(|_| SystemTime::now()),
+                modified_at: metadata.modified().unwrap_or_else(|_| SystemTime::now()),
+                metadata: HashMap::new(),
+                // The file is already in the files directory, where it came from is unknown.
+                original_path: None,
+            };
+            store.index_title(id, &file.title);
+            store.files.insert(id, file);
+
+            // New files should not get the ids of the ones we found.
+            store.allocator.mark_used(id.0);
         }
+
+        Ok(store)
     }
 
     /// Creates a new reference to a file, and returns the FileId as well as the filename that
     /// the file should be saved as.
     /// The filename is not dependant on the file's title.
-    pub fn new_file(&mut self, title: &str, extension: KnownExtension) -> (FileId, PathBuf) {
-        let id = self.next_id;
-        let new_file = File {
-            id,
-            title: title.to_string(),
-            extension,
-            system_tags: HashSet::new(),
+    /// Will return an error when there are no more ids left to hand out.
+    pub fn new_file(
+        &mut self,
+        title: &str,
+        extension: KnownExtension,
+    ) -> Result<(FileId, PathBuf)> {
+        let file = self.new_file_ref(title, extension)?;
+        Ok((file.id, file.file_name()))
+    }
+
+    /// Like `new_file`, but gives back the newly created file itself.
+    pub fn new_file_ref(&mut self, title: &str, extension: KnownExtension) -> Result<&File> {
+        let title = validate_title(title)?;
+        let id = self.next_free_id()?;
+
+        // Store the new file. The id is new, so this always inserts.
+        self.index_title(id, title);
+        Ok(self
+            .files
+            .entry(id)
+            .or_insert_with(|| File::new(id, title, extension)))
+    }
+
+    /// Hands out the next id that is not taken yet.
+    fn next_free_id(&mut self) -> Result<FileId> {
+        loop {
+            let id = FileId(self.allocator.allocate().context("Ran out of file ids.")?);
+            if !self.files.contains_key(&id) {
+                return Ok(id);
+            }
+        }
+    }
+
+    /// Adds a file under the given id, instead of handing out a new one.
+    /// Ids handed out after this come after the given id.
+    /// Will return an error when the id is already taken, or the title is not valid.
+    pub fn insert_with_id(
+        &mut self,
+        id: FileId,
+        title: &str,
+        extension: KnownExtension,
+    ) -> Result<()> {
+        if self.files.contains_key(&id) {
+            bail!("File id {} is already taken.", id);
+        }
+        let title = validate_title(title)?;
+
+        self.index_title(id, title);
+        self.files.insert(id, File::new(id, title, extension));
+        self.allocator.mark_used(id.0);
+
+        Ok(())
+    }
+
+    /// Changes the title of a file. Surrounding whitespace is removed.
+    /// The file on disk is named after the id, so it stays where it is.
+    /// Returns false if the file does not exist, or if the title is not valid,
+    /// see `validate_title`.
+    pub fn set_title(&mut self, id: FileId, title: &str) -> bool {
+        let title = match validate_title(title) {
+            Ok(title) => title,
+            Err(_) => return false,
+        };
+        let old_title = match self.files.get_mut(&id) {
+            Some(file) => {
+                file.modified_at = SystemTime::now();
+                std::mem::replace(&mut file.title, title.to_string())
+            }
+            None => return false,
         };
-        let file_name = new_file.file_name();
+        self.unindex_title(id, &old_title);
+        self.index_title(id, title);
+        true
+    }
+
+    /// Finds all the files with exactly the given title, ignoring case and surrounding
+    /// whitespace. Unlike `search_titles`, this does not have to go through all the files.
+    /// The ids are sorted.
+    pub fn exact_title_lookup(&self, title: &str) -> Vec<FileId> {
+        let mut found: Vec<FileId> = self
+            .title_index
+            .get(&normalize_title(title))
+            .map(|ids| ids.iter().copied().collect())
+            .unwrap_or_default();
+
+        found.sort();
+        found
+    }
+
+    fn index_title(&mut self, id: FileId, title: &str) {
+        self.title_index
+            .entry(normalize_title(title))
+            .or_default()
+            .insert(id);
+    }
+
+    fn unindex_title(&mut self, id: FileId, title: &str) {
+        let key = normalize_title(title);
+        if let Some(ids) = self.title_index.get_mut(&key) {
+            ids.remove(&id);
+            if ids.is_empty() {
+                self.title_index.remove(&key);
+            }
+        }
+    }
+
+    /// Records the hash of the file's contents, see `File::content_hash`.
+    /// Returns whether the file exists.
+    pub fn set_content_hash(&mut self, id: FileId, hash: &str) -> bool {
+        match self.files.get_mut(&id) {
+            Some(file) => {
+                file.content_hash = Some(hash.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Changes the extension of a file, like after converting it to a different format.
+    /// Moving the file itself is up to the caller, see `File::file_name`.
+    /// Returns whether the file exists.
+    pub fn set_extension(&mut self, id: FileId, extension: KnownExtension) -> bool {
+        match self.files.get_mut(&id) {
+            Some(file) => {
+                file.extension = extension;
+                file.modified_at = SystemTime::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records where the file was imported from, see `File::original_path`.
+    /// Returns whether the file exists.
+    pub fn set_original_path(&mut self, id: FileId, path: &Path) -> bool {
+        match self.files.get_mut(&id) {
+            Some(file) => {
+                file.original_path = Some(path.to_path_buf());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records how big the file is on disk, see `File::size_bytes`.
+    /// Returns whether the file exists.
+    pub fn set_size_bytes(&mut self, id: FileId, size_bytes: u64) -> bool {
+        match self.files.get_mut(&id) {
+            Some(file) => {
+                file.size_bytes = size_bytes;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records the width and height of an image file, see `File::dimensions`.
+    /// Returns whether the file exists.
+    pub fn set_dimensions(&mut self, id: FileId, dimensions: (u32, u32)) -> bool {
+        match self.files.get_mut(&id) {
+            Some(file) => {
+                file.dimensions = Some(dimensions);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records the perceptual hash of an image file, see `File::perceptual_hash`.
+    /// Returns whether the file exists.
+    pub fn set_perceptual_hash(&mut self, id: FileId, hash: u64) -> bool {
+        match self.files.get_mut(&id) {
+            Some(file) => {
+                file.perceptual_hash = Some(hash);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Finds all the files that have the query somewhere in their title, ignoring case.
+    /// The ids are sorted.
+    pub fn search_titles(&self, query: &str) -> Vec<FileId> {
+        let query = query.to_lowercase();
+        let mut found: Vec<FileId> = self
+            .files
+            .values()
+            .filter(|file| file.title.to_lowercase().contains(&query))
+            .map(|file| file.id)
+            .collect();
+
+        found.sort();
+        found
+    }
+
+    /// All the files, sorted by id.
+    /// The ids are still all sorted up front, but only references are collected,
+    /// the files themselves are not cloned.
+    pub fn iter_sorted_by_id(&self) -> impl Iterator<Item = (&FileId, &File)> {
+        let mut files: Vec<(&FileId, &File)> = self.files.iter().collect();
+        files.sort_by_key(|(id, _)| **id);
+        files.into_iter()
+    }
+
+    /// The files sorted by id, skipping the first `offset` and taking at most `limit` of them.
+    /// Going past the end gives fewer files, or none at all.
+    pub fn page(&self, offset: usize, limit: usize) -> Vec<(FileId, &File)> {
+        let mut ids: Vec<FileId> = self.files.keys().copied().collect();
+        ids.sort();
+
+        ids.into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|id| (id, &self.files[&id]))
+            .collect()
+    }
+
+    /// Finds a file with the given content hash, if there is one.
+    pub fn find_by_content_hash(&self, hash: &str) -> Option<FileId> {
+        self.files
+            .values()
+            .find(|file| file.content_hash.as_deref() == Some(hash))
+            .map(|file| file.id)
+    }
+
+    /// Roughly how much memory the store takes up, in bytes, for diagnostics.
+    /// Counts the reserved room of the maps and the strings and sets of the files,
+    /// but not the overhead of the allocator or the hash maps' control bytes.
+    pub fn estimated_memory_bytes(&self) -> usize {
+        let files = self.files.capacity() * size_of::<(FileId, File)>()
+            + self
+                .files
+                .values()
+                .map(|file| {
+                    file.title.capacity()
+                        + file.system_tags.capacity() * size_of::<SystemTag>()
+                        + file.content_hash.as_ref().map_or(0, |hash| hash.capacity())
+                        + file.metadata.capacity() * size_of::<(String, String)>()
+                        + file
+                            .metadata
+                            .iter()
+                            .map(|(key, value)| key.capacity() + value.capacity())
+                            .sum::<usize>()
+                })
+                .sum::<usize>();
+
+        let title_index = self.title_index.capacity() * size_of::<(String, HashSet<FileId>)>()
+            + self
+                .title_index
+                .iter()
+                .map(|(title, ids)| title.capacity() + ids.capacity() * size_of::<FileId>())
+                .sum::<usize>();
+
+        size_of::<FileStore>() + files + title_index
+    }
+
+    /// Counts how many files there are of each extension, keyed by `KnownExtension::to_str`.
+    /// Extensions without any files are left out.
+    pub fn count_by_extension(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for file in self.files.values() {
+            *counts
+                .entry(file.extension.to_str().to_string())
+                .or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// All the files with the given extension, in no particular order.
+    pub fn iter_by_extension<'a>(
+        &'a self,
+        extension: &'a KnownExtension,
+    ) -> impl Iterator<Item = (&'a FileId, &'a File)> + 'a {
+        self.files
+            .iter()
+            .filter(move |(_, file)| &file.extension == extension)
+    }
+
+    /// Finds all the files that have the given system tag. The ids are sorted.
+    pub fn find_by_system_tag(&self, tag: SystemTag) -> Vec<FileId> {
+        let mut found: Vec<FileId> = self
+            .files
+            .values()
+            .filter(|file| file.system_tags.contains(&tag))
+            .map(|file| file.id)
+            .collect();
+
+        found.sort();
+        found
+    }
+
+    /// Adds a system tag to a file. Adding a tag that the file already has changes nothing.
+    /// Returns whether the tag was added: false if the file does not exist or already has it.
+    pub fn add_system_tag(&mut self, id: FileId, tag: SystemTag) -> bool {
+        match self.files.get_mut(&id) {
+            Some(file) => {
+                let added = file.system_tags.insert(tag);
+                if added {
+                    file.modified_at = SystemTime::now();
+                }
+                added
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a system tag from a file, if it has it.
+    /// Returns whether the file exists.
+    pub fn remove_system_tag(&mut self, id: FileId, tag: SystemTag) -> bool {
+        match self.files.get_mut(&id) {
+            Some(file) => {
+                if file.system_tags.remove(&tag) {
+                    file.modified_at = SystemTime::now();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Sets a metadata value of a file, replacing the value the key had.
+    /// Returns whether the file exists.
+    pub fn set_metadata(&mut self, id: FileId, key: &str, value: &str) -> bool {
+        match self.files.get_mut(&id) {
+            Some(file) => {
+                file.metadata.insert(key.to_string(), value.to_string());
+                file.modified_at = SystemTime::now();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The metadata value of a file for the given key.
+    /// None when the file does not exist or does not have the key.
+    pub fn get_metadata(&self, id: FileId, key: &str) -> Option<&str> {
+        self.files
+            .get(&id)
+            .and_then(|file| file.metadata.get(key))
+            .map(String::as_str)
+    }
+
+    /// Removes a metadata key from a file, returning the value it had.
+    pub fn remove_metadata(&mut self, id: FileId, key: &str) -> Option<String> {
+        let file = self.files.get_mut(&id)?;
+        let removed = file.metadata.remove(key)?;
+        file.modified_at = SystemTime::now();
+        Some(removed)
+    }
+}
+
+impl Default for FileStore {
+    fn default() -> FileStore {
+        FileStore::new()
+    }
+}
+
+impl MutableStore for FileStore {
+    /// Will return an error when the title of the file is not valid, see `validate_title`.
+    fn insert(&mut self, mut file: File) -> Result<FileId> {
+        file.title = validate_title(&file.title)?.to_string();
+        let id = self.next_free_id()?;
+        file.id = id;
+
+        self.index_title(id, &file.title);
+        self.files.insert(id, file);
 
-        // Store the new file.
-        self.files.insert(id, new_file);
+        Ok(id)
+    }
+}
 
-        // Update where we are at with the ids.
-        self.next_id = FileId(id.0 + 1);
+/// Consumes the store, yielding the files by value, in no particular order.
+impl IntoIterator for FileStore {
+    type Item = (FileId, File);
+    type IntoIter = std::collections::hash_map::IntoIter<FileId, File>;
 
-        (id, file_name)
+    fn into_iter(self) -> Self::IntoIter {
+        self.files.into_iter()
     }
 }
 
@@ -61,27 +594,111 @@ impl IndexedStore for FileStore {
         self.files.get(&id)
     }
 
+    fn get_mut(&mut self, id: FileId) -> Option<&mut File> {
+        self.files.get_mut(&id)
+    }
+
     fn count(&self) -> usize {
         self.files.len()
     }
 
+    fn item_kind(&self) -> &'static str {
+        "file"
+    }
+
+    fn contains(&self, id: FileId) -> bool {
+        self.files.contains_key(&id)
+    }
+
     fn remove(&mut self, id: &Self::Id) -> Option<Self::Item> {
-        self.files.remove(id)
+        let file = self.files.remove(id)?;
+        self.unindex_title(*id, &file.title);
+        Some(file)
     }
 
-    fn iter(&self) -> Iter<Self::Id, Self::Item> {
-        self.files.iter()
+    fn clear(&mut self) {
+        self.files.clear();
+        self.title_index.clear();
+    }
+
+    fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&FileId, &File) -> bool,
+    {
+        let mut removed = Vec::new();
+        self.files.retain(|id, file| {
+            let kept = keep(id, file);
+            if !kept {
+                removed.push((*id, file.title.clone()));
+            }
+            kept
+        });
+
+        for (id, title) in removed {
+            self.unindex_title(id, &title);
+        }
+    }
+
+    fn iter(&self) -> StoreIter<'_, Self::Id, Self::Item> {
+        Box::new(self.files.iter())
     }
 }
 
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct File {
     id: FileId,
     title: String,
     extension: KnownExtension,
     system_tags: HashSet<SystemTag>,
+    /// Hash of the file's contents, used to prevent storing the same file twice.
+    #[cfg_attr(feature = "serde", serde(default))]
+    content_hash: Option<String>,
+    /// Width and height, for images.
+    #[cfg_attr(feature = "serde", serde(default))]
+    dimensions: Option<(u32, u32)>,
+    /// Hash of what the image looks like, to find near-duplicates with.
+    #[cfg_attr(feature = "serde", serde(default))]
+    perceptual_hash: Option<u64>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    size_bytes: u64,
+    #[cfg_attr(feature = "serde", serde(default = "unknown_time"))]
+    created_at: SystemTime,
+    /// When the title, tags or metadata last changed.
+    #[cfg_attr(feature = "serde", serde(default = "unknown_time"))]
+    modified_at: SystemTime,
+    /// Free-form key/value pairs, like the author or the license.
+    #[cfg_attr(feature = "serde", serde(default))]
+    metadata: HashMap<String, String>,
+    /// Where the file was imported from, for re-importing and auditing.
+    #[cfg_attr(feature = "serde", serde(default))]
+    original_path: Option<PathBuf>,
+}
+
+/// Used for files and assets from before we kept track of time.
+#[cfg(feature = "serde")]
+pub(super) fn unknown_time() -> SystemTime {
+    SystemTime::UNIX_EPOCH
 }
 
 impl File {
+    fn new(id: FileId, title: &str, extension: KnownExtension) -> File {
+        let now = SystemTime::now();
+        File {
+            id,
+            title: title.to_string(),
+            extension,
+            system_tags: HashSet::new(),
+            content_hash: None,
+            dimensions: None,
+            perceptual_hash: None,
+            size_bytes: 0,
+            created_at: now,
+            modified_at: now,
+            metadata: HashMap::new(),
+            original_path: None,
+        }
+    }
+
     pub fn title(&self) -> &str {
         self.title.as_str()
     }
@@ -91,50 +708,214 @@ impl File {
 
     /// The file name is not dependant on the file's title.
     pub fn file_name(&self) -> PathBuf {
-        PathBuf::new()
-            .with_file_name(self.id.to_string())
-            .with_extension(self.extension.to_str())
+        PathBuf::from(self.id.to_string()).with_extension(self.extension.to_str())
     }
 
     pub fn system_tags(&self) -> &HashSet<SystemTag> {
         &self.system_tags
     }
+
+    /// Whether the image has see-through pixels, see `SystemTag::Transparent`.
+    /// Files that could not be decoded count as opaque.
+    pub fn is_transparent(&self) -> bool {
+        self.system_tags.contains(&SystemTag::Transparent)
+    }
+
+    /// When the file was added to the store.
+    pub fn created_at(&self) -> SystemTime {
+        self.created_at
+    }
+
+    /// When the title, tags or metadata of the file last changed.
+    /// The same as `created_at` for files that were never changed.
+    pub fn modified_at(&self) -> SystemTime {
+        self.modified_at
+    }
+
+    /// How big the file is on disk.
+    pub fn size_bytes(&self) -> u64 {
+        self.size_bytes
+    }
+
+    /// Width and height of the image, in pixels.
+    /// None when the file could not be decoded as an image.
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        self.dimensions
+    }
+
+    /// Hash of what the image looks like, see `Data::find_similar`.
+    /// None when the phash feature is off, or when the file could not be decoded as an image.
+    pub fn perceptual_hash(&self) -> Option<u64> {
+        self.perceptual_hash
+    }
+
+    /// SHA-256 hash of the file's contents, as lowercase hex.
+    /// None when the hash has not been recorded.
+    pub fn content_hash(&self) -> Option<&str> {
+        self.content_hash.as_deref()
+    }
+
+    /// The path the file was imported from, as it was given when adding it from disk.
+    /// None for files that were added from bytes or a reader.
+    pub fn original_path(&self) -> Option<&Path> {
+        self.original_path.as_deref()
+    }
+
+    /// All the metadata of the file, see `FileStore::set_metadata`.
+    pub fn metadata(&self) -> &HashMap<String, String> {
+        &self.metadata
+    }
 }
 /// File extensions that we know how to deal with.
 #[derive(Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum KnownExtension {
     Png,
+    /// Both "jpg" and "jpeg" files, stored as "jpg".
+    Jpeg,
+    Gif,
+    Bmp,
+    WebP,
+    /// Vector images, which are stored but not decoded.
+    Svg,
+    /// Both "tif" and "tiff" files, stored as "tiff".
+    Tiff,
+    /// Icons can hold multiple sizes of the same image.
+    /// When decoding them, for example to find the dimensions, the largest one is used.
+    Ico,
 }
 
 impl KnownExtension {
+    /// Every extension we know how to deal with, for example to filter a file picker with.
+    pub fn all() -> &'static [KnownExtension] {
+        // When adding a variant, also add it here.
+        &[
+            Self::Png,
+            Self::Jpeg,
+            Self::Gif,
+            Self::Bmp,
+            Self::WebP,
+            Self::Svg,
+            Self::Tiff,
+            Self::Ico,
+        ]
+    }
+
+    /// The strings of all the extensions, see `KnownExtension::all` and `KnownExtension::to_str`.
+    /// Only has "jpg" for jpeg files, even though "jpeg" is accepted as well.
+    pub fn all_strings() -> Vec<&'static str> {
+        Self::all()
+            .iter()
+            .map(|extension| extension.to_str())
+            .collect()
+    }
+
     /// Creates a KnownExtension from a given extension string (without the ".").
     /// Returns None when we don't know how to deal with a given type of file.
     pub fn from_str(string: &str) -> Option<KnownExtension> {
         match string.to_ascii_lowercase().as_str() {
             "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "gif" => Some(Self::Gif),
+            "bmp" => Some(Self::Bmp),
+            "webp" => Some(Self::WebP),
+            "svg" => Some(Self::Svg),
+            "tif" | "tiff" => Some(Self::Tiff),
+            "ico" => Some(Self::Ico),
             _ => None,
         }
     }
 
     /// Creates a KnownExtension from a given Path.
     /// Returns None when we don't know how to deal with a given type of file.
+    /// Only the part after the last dot counts, so "sprite.tar.png" is a png.
+    /// Dotfiles like ".png" have no extension, and whitespace is not trimmed,
+    /// so "sprite.png " is not a png either.
     pub fn from_path(path: &Path) -> Option<KnownExtension> {
         Self::from_str(path.extension().unwrap_or_default().to_str().unwrap_or(""))
     }
 
-    pub fn to_str(&self) -> &str {
+    pub fn to_str(&self) -> &'static str {
         match self {
             Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Gif => "gif",
+            Self::Bmp => "bmp",
+            Self::WebP => "webp",
+            Self::Svg => "svg",
+            Self::Tiff => "tiff",
+            Self::Ico => "ico",
+        }
+    }
+
+    /// The MIME type of files with this extension, for example for a `Content-Type` header.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Gif => "image/gif",
+            Self::Bmp => "image/bmp",
+            Self::WebP => "image/webp",
+            Self::Svg => "image/svg+xml",
+            Self::Tiff => "image/tiff",
+            Self::Ico => "image/x-icon",
+        }
+    }
+
+    /// Whether files with this extension are raster images that we decode, to find out
+    /// things like their dimensions.
+    pub fn is_image(&self) -> bool {
+        match self {
+            Self::Png
+            | Self::Jpeg
+            | Self::Gif
+            | Self::Bmp
+            | Self::WebP
+            | Self::Tiff
+            | Self::Ico => true,
+            Self::Svg => false,
+        }
+    }
+
+    /// Whether files with this extension are vector images.
+    /// These can't be decoded, so there are no dimensions or thumbnails for them.
+    pub fn is_vector(&self) -> bool {
+        match self {
+            Self::Svg => true,
+            Self::Png
+            | Self::Jpeg
+            | Self::Gif
+            | Self::Bmp
+            | Self::WebP
+            | Self::Tiff
+            | Self::Ico => false,
         }
     }
 }
 
+/// Shows the extension as it is used in file names.
+impl fmt::Display for KnownExtension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.to_str())
+    }
+}
+
 #[derive(Eq, PartialEq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum SystemTag {
     /// Indicates an image that has some kind of transparency to it.
     Transparent,
 }
 
+impl SystemTag {
+    /// Name of the tag, for showing it to people.
+    pub fn to_str(self) -> &'static str {
+        match self {
+            Self::Transparent => "transparent",
+        }
+    }
+}
+
 #[cfg(test)]
 mod test_file_store {
     use super::*;
@@ -144,9 +925,9 @@ mod test_file_store {
     fn new_files_should_have_different_ids_and_paths() {
         let mut store = FileStore::new();
 
-        let (id_1, path_1) = store.new_file("test file", KnownExtension::Png);
-        let (id_2, path_2) = store.new_file("SDKDKK@K@@", KnownExtension::Png);
-        let (id_3, path_3) = store.new_file("test {}", KnownExtension::Png);
+        let (id_1, path_1) = store.new_file("test file", KnownExtension::Png).unwrap();
+        let (id_2, path_2) = store.new_file("SDKDKK@K@@", KnownExtension::Png).unwrap();
+        let (id_3, path_3) = store.new_file("test {}", KnownExtension::Png).unwrap();
 
         assert_ne!(id_1, id_2, "Assigned ids must be unique.");
         assert_ne!(id_2, id_3, "Assigned ids must be unique.");
@@ -157,16 +938,70 @@ mod test_file_store {
         assert_ne!(path_3, path_1, "Assigned paths must be unique.");
     }
 
+    /// Once the ids run out, new files should be refused instead of reusing ids.
+    #[test]
+    fn running_out_of_ids_fails() {
+        let mut store = FileStore::with_next_id(u32::MAX - 1);
+
+        let (id, _) = store.new_file("last", KnownExtension::Png).unwrap();
+        assert_eq!(id, FileId(u32::MAX - 1));
+        assert!(store.new_file("one too many", KnownExtension::Png).is_err());
+        assert_eq!(store.count(), 1);
+    }
+
+    /// Hands out every `step`th id, starting at `next`, like one shard out of `step`.
+    struct ShardAllocator {
+        next: u32,
+        step: u32,
+    }
+
+    impl IdAllocator for ShardAllocator {
+        fn allocate(&mut self) -> Option<u32> {
+            let id = self.next;
+            self.next = id.checked_add(self.step)?;
+            Some(id)
+        }
+
+        fn mark_used(&mut self, _id: u32) {}
+
+        fn next_id(&self) -> u32 {
+            self.next
+        }
+    }
+
+    #[test]
+    fn custom_allocator_decides_the_ids() {
+        let mut store = FileStore::with_allocator(Box::new(ShardAllocator {
+            next: 1000,
+            step: 10,
+        }));
+
+        let (first, path) = store.new_file("first", KnownExtension::Png).unwrap();
+        assert_eq!(first, FileId(1000));
+        assert_eq!(path, Path::new("1000.png"));
+
+        // Ids that are taken already are skipped.
+        store
+            .insert_with_id(FileId(1010), "taken", KnownExtension::Png)
+            .unwrap();
+        let (second, _) = store.new_file("second", KnownExtension::Png).unwrap();
+        assert_eq!(second, FileId(1020));
+
+        store.set_allocator(Box::new(SequentialAllocator::starting_at(1010)));
+        let (third, _) = store.new_file("third", KnownExtension::Png).unwrap();
+        assert_eq!(third, FileId(1011));
+    }
+
     /// When adding files, the file count should go up.
     #[test]
     fn adding_files_increases_count() {
         let mut store = FileStore::new();
 
-        store.new_file("!!!", KnownExtension::Png);
+        store.new_file("!!!", KnownExtension::Png).unwrap();
         assert_eq!(store.count(), 1);
-        store.new_file("BLAA!", KnownExtension::Png);
+        store.new_file("BLAA!", KnownExtension::Png).unwrap();
         assert_eq!(store.count(), 2);
-        store.new_file("meep!", KnownExtension::Png);
+        store.new_file("meep!", KnownExtension::Png).unwrap();
         assert_eq!(store.count(), 3);
     }
 
@@ -174,7 +1009,7 @@ mod test_file_store {
     fn getting_files_returns_correct_values() {
         let mut store = FileStore::new();
 
-        let (new_id, new_name) = store.new_file("!@@#$@#@", KnownExtension::Png);
+        let (new_id, new_name) = store.new_file("!@@#$@#@", KnownExtension::Png).unwrap();
         let file = store.get(new_id).unwrap();
 
         // Retrieved file name must be the same as the one returned on creation.
@@ -190,34 +1025,803 @@ mod test_file_store {
         // Getting a non-existing file must return None.
         assert!(store.get(FileId(10)).is_none());
     }
-}
 
-#[cfg(test)]
-mod test_file_extensions {
-    use super::*;
-    use naughty_strings::BLNS;
+    #[test]
+    fn contains_and_is_empty() {
+        let mut store = FileStore::new();
+        assert!(store.is_empty());
+        assert!(!store.contains(FileId(0)));
+
+        let (id, _) = store.new_file("file", KnownExtension::Png).unwrap();
+        assert!(!store.is_empty());
+        assert!(store.contains(id));
+        assert!(!store.contains(FileId(10)));
+    }
 
     #[test]
-    fn unknown_file_extensions_should_return_none() {
-        assert!(KnownExtension::from_str("pdf").is_none());
-        assert!(KnownExtension::from_str("xcf").is_none());
-        assert!(KnownExtension::from_str("jpg").is_none());
+    fn changes_through_get_mut_are_visible() {
+        let mut store = FileStore::new();
+        let (id, _) = store.new_file("old title", KnownExtension::Png).unwrap();
+
+        store.get_mut(id).unwrap().title = "new title".to_string();
+        assert_eq!(store.get(id).unwrap().title(), "new title");
+
+        assert!(store.get_mut(FileId(10)).is_none());
     }
 
     #[test]
-    fn file_extensions_should_work_when_capitalized() {
-        assert_eq!(
-            KnownExtension::from_str("PNG").unwrap(),
-            KnownExtension::Png
-        );
-        assert_eq!(
-            KnownExtension::from_str("pnG").unwrap(),
-            KnownExtension::Png
-        );
-        assert_eq!(
-            KnownExtension::from_str("PnG").unwrap(),
-            KnownExtension::Png
+    fn find_files_by_content_hash() {
+        let mut store = FileStore::new();
+        let (id_1, _) = store.new_file("first", KnownExtension::Png).unwrap();
+        let (id_2, _) = store.new_file("second", KnownExtension::Png).unwrap();
+
+        assert!(store.set_content_hash(id_1, "aaaa"));
+        assert!(store.set_content_hash(id_2, "bbbb"));
+        assert!(!store.set_content_hash(FileId(10), "cccc"));
+
+        assert_eq!(store.get(id_1).unwrap().content_hash(), Some("aaaa"));
+        assert_eq!(store.find_by_content_hash("bbbb"), Some(id_2));
+        assert_eq!(store.find_by_content_hash("cccc"), None);
+    }
+
+    #[test]
+    fn search_titles_ignores_case() {
+        let mut store = FileStore::new();
+        let (sword, _) = store.new_file("Big Sword", KnownExtension::Png).unwrap();
+        let (swords, _) = store.new_file("two swords", KnownExtension::Png).unwrap();
+        let (shield, _) = store.new_file("Shield", KnownExtension::Png).unwrap();
+
+        assert_eq!(store.search_titles("SWORD"), vec![sword, swords]);
+        assert_eq!(store.search_titles("ield"), vec![shield]);
+        assert!(store.search_titles("axe").is_empty());
+        assert_eq!(store.search_titles(""), vec![sword, swords, shield]);
+    }
+
+    #[test]
+    fn pages_cover_all_files_once() {
+        let mut store = FileStore::new();
+        let mut ids: Vec<FileId> = (0..7)
+            .map(|i| {
+                store
+                    .new_file(&i.to_string(), KnownExtension::Png)
+                    .unwrap()
+                    .0
+            })
+            .collect();
+        ids.sort();
+
+        let mut seen = Vec::new();
+        for offset in (0..7).step_by(3) {
+            let page = store.page(offset, 3);
+            assert!(page.len() <= 3);
+            for (id, file) in page {
+                assert_eq!(file.id, id);
+                seen.push(id);
+            }
+        }
+        assert_eq!(seen, ids);
+
+        assert_eq!(store.page(6, 100).len(), 1);
+        assert!(store.page(7, 3).is_empty());
+        assert!(store.page(usize::MAX, usize::MAX).is_empty());
+        assert!(store.page(0, 0).is_empty());
+    }
+
+    #[test]
+    fn iterate_sorted_by_id() {
+        let mut store = FileStore::new();
+        for i in 0..20 {
+            store.new_file(&i.to_string(), KnownExtension::Png).unwrap();
+        }
+        store.remove(&FileId(4));
+
+        let ids: Vec<FileId> = store.iter_sorted_by_id().map(|(id, _)| *id).collect();
+        assert_eq!(ids.len(), 19);
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn memory_estimate_grows_with_the_files() {
+        let mut store = FileStore::new();
+        let empty = store.estimated_memory_bytes();
+        assert!(empty > 0);
+
+        let long_title = "a very long title ".repeat(100);
+        for i in 0..50 {
+            let (id, _) = store
+                .new_file(&format!("{} {}", long_title, i), KnownExtension::Png)
+                .unwrap();
+            store.add_system_tag(id, SystemTag::Transparent);
+        }
+
+        // Every title is in the files and in the title index.
+        assert!(store.estimated_memory_bytes() > empty + 2 * 50 * long_title.len());
+    }
+
+    #[test]
+    fn perceptual_hash_is_recorded() {
+        let mut store = FileStore::new();
+        let (id, _) = store.new_file("Sword", KnownExtension::Png).unwrap();
+        assert_eq!(store.get(id).unwrap().perceptual_hash(), None);
+
+        assert!(store.set_perceptual_hash(id, 0xf0f0));
+        assert_eq!(store.get(id).unwrap().perceptual_hash(), Some(0xf0f0));
+        assert!(!store.set_perceptual_hash(FileId(3), 0xf0f0));
+    }
+
+    #[test]
+    fn metadata_can_be_set_overwritten_and_removed() {
+        let mut store = FileStore::new();
+        let (id, _) = store.new_file("Sword", KnownExtension::Png).unwrap();
+
+        assert!(store.set_metadata(id, "author", "x"));
+        assert!(store.set_metadata(id, "license", "CC0"));
+        assert!(store.set_metadata(id, "author", "y"));
+        assert_eq!(store.get_metadata(id, "author"), Some("y"));
+        assert_eq!(store.get(id).unwrap().metadata().len(), 2);
+
+        assert_eq!(
+            store.remove_metadata(id, "license"),
+            Some("CC0".to_string())
+        );
+        assert_eq!(store.remove_metadata(id, "license"), None);
+        assert_eq!(store.get_metadata(id, "license"), None);
+
+        assert!(!store.set_metadata(FileId(3), "author", "x"));
+        assert_eq!(store.get_metadata(FileId(3), "author"), None);
+    }
+
+    #[test]
+    fn changes_update_modified_at() {
+        let mut store = FileStore::new();
+        let (id, _) = store.new_file("Sword", KnownExtension::Png).unwrap();
+        let created_at = store.get(id).unwrap().created_at();
+        assert_eq!(store.get(id).unwrap().modified_at(), created_at);
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        store.set_title(id, "Big sword");
+        let renamed_at = store.get(id).unwrap().modified_at();
+        assert!(renamed_at > created_at);
+        assert_eq!(store.get(id).unwrap().created_at(), created_at);
+
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        store.set_metadata(id, "license", "CC0");
+        assert!(store.get(id).unwrap().modified_at() > renamed_at);
+
+        // Nothing to remove, so nothing changes.
+        let changed_at = store.get(id).unwrap().modified_at();
+        store.remove_system_tag(id, SystemTag::Transparent);
+        store.remove_metadata(id, "author");
+        assert_eq!(store.get(id).unwrap().modified_at(), changed_at);
+    }
+
+    #[test]
+    fn ids_and_values_yield_everything() {
+        let mut store = FileStore::new();
+        let (id_1, _) = store.new_file("first", KnownExtension::Png).unwrap();
+        let (id_2, _) = store.new_file("second", KnownExtension::Png).unwrap();
+
+        let mut ids: Vec<FileId> = store.ids().copied().collect();
+        ids.sort();
+        assert_eq!(ids, vec![id_1, id_2]);
+
+        let mut titles: Vec<&str> = store.values().map(|file| file.title()).collect();
+        titles.sort();
+        assert_eq!(titles, vec!["first", "second"]);
+    }
+
+    #[test]
+    fn insert_with_explicit_id() {
+        let mut store = FileStore::new();
+        let (first, _) = store.new_file("first", KnownExtension::Png).unwrap();
+
+        store
+            .insert_with_id(FileId(10), "explicit", KnownExtension::Png)
+            .unwrap();
+        assert_eq!(store.get(FileId(10)).unwrap().title(), "explicit");
+        assert!(store
+            .insert_with_id(first, "taken", KnownExtension::Png)
+            .is_err());
+        assert_eq!(store.get(first).unwrap().title(), "first");
+
+        // Ids below next_id don't move it back.
+        store
+            .insert_with_id(FileId(5), "lower", KnownExtension::Png)
+            .unwrap();
+        let (next, _) = store.new_file("next", KnownExtension::Png).unwrap();
+        assert_eq!(next, FileId(11));
+    }
+
+    #[test]
+    fn last_explicit_id_is_not_handed_out_again() {
+        let mut store = FileStore::new();
+        store
+            .insert_with_id(FileId(u32::MAX), "last", KnownExtension::Png)
+            .unwrap();
+
+        assert!(store.new_file("one too many", KnownExtension::Png).is_err());
+        assert_eq!(store.get(FileId(u32::MAX)).unwrap().title(), "last");
+    }
+
+    #[test]
+    fn new_file_ref_returns_the_stored_file() {
+        let mut store = FileStore::new();
+        let file = store.new_file_ref("file", KnownExtension::Gif).unwrap();
+        let (id, title, file_name) = (file.id, file.title().to_string(), file.file_name());
+
+        let stored = store.get(id).unwrap();
+        assert_eq!(stored.title(), title);
+        assert_eq!(stored.extension(), &KnownExtension::Gif);
+        assert_eq!(stored.file_name(), file_name);
+    }
+
+    #[test]
+    fn insert_through_a_trait_object() {
+        let mut source = FileStore::new();
+        source.new_file("first", KnownExtension::Png).unwrap();
+        source.new_file("second", KnownExtension::Gif).unwrap();
+
+        let mut dest = FileStore::with_next_id(10);
+        let store: &mut dyn MutableStore<Id = FileId, Item = File> = &mut dest;
+        let mut ids: Vec<FileId> = source
+            .into_iter()
+            .map(|(_, file)| store.insert(file).unwrap())
+            .collect();
+        ids.sort();
+
+        assert_eq!(ids, vec![FileId(10), FileId(11)]);
+        for id in ids {
+            assert_eq!(store.get(id).unwrap().id, id);
+        }
+        assert_eq!(dest.exact_title_lookup("second").len(), 1);
+    }
+
+    #[test]
+    fn get_many_keeps_the_order_of_the_ids() {
+        let mut store = FileStore::new();
+        let (id_1, _) = store.new_file("first", KnownExtension::Png).unwrap();
+        let (id_2, _) = store.new_file("second", KnownExtension::Png).unwrap();
+
+        let found = store.get_many(&[id_2, FileId(7), id_1, id_2]);
+        let titles: Vec<Option<&str>> = found.iter().map(|file| file.map(File::title)).collect();
+        assert_eq!(
+            titles,
+            vec![Some("second"), None, Some("first"), Some("second")]
+        );
+        assert!(store.get_many(&[]).is_empty());
+    }
+
+    #[test]
+    fn default_is_empty() {
+        let store: FileStore = Default::default();
+        assert_eq!(store.count(), 0);
+    }
+
+    #[test]
+    fn with_capacity_behaves_like_new() {
+        let mut store = FileStore::with_capacity(100);
+        assert!(store.files.capacity() >= 100);
+        assert!(store.is_empty());
+
+        for i in 0..100 {
+            let (id, _) = store.new_file(&i.to_string(), KnownExtension::Png).unwrap();
+            assert_eq!(id, FileId(i));
+        }
+        assert_eq!(store.count(), 100);
+        assert_eq!(store.get(FileId(42)).unwrap().title(), "42");
+    }
+
+    #[test]
+    fn into_iter_yields_every_file_once() {
+        let mut store = FileStore::new();
+        let (id_1, _) = store.new_file("first", KnownExtension::Png).unwrap();
+        let (id_2, _) = store.new_file("second", KnownExtension::Jpeg).unwrap();
+
+        let mut files: Vec<(FileId, File)> = store.into_iter().collect();
+        files.sort_by_key(|(id, _)| *id);
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].0, id_1);
+        assert_eq!(files[0].1.title(), "first");
+        assert_eq!(files[1].0, id_2);
+        assert_eq!(files[1].1.title(), "second");
+    }
+
+    #[test]
+    fn exact_title_lookup_finds_all_files_with_the_title() {
+        let mut store = FileStore::new();
+        let (sword_1, _) = store.new_file("Sword", KnownExtension::Png).unwrap();
+        let (sword_2, _) = store.new_file("  sword ", KnownExtension::Png).unwrap();
+        store.new_file("Swords", KnownExtension::Png).unwrap();
+
+        assert_eq!(store.exact_title_lookup("SWORD"), vec![sword_1, sword_2]);
+        assert!(store.exact_title_lookup("swo").is_empty());
+    }
+
+    #[test]
+    fn title_index_follows_renames_and_removals() {
+        let mut store = FileStore::new();
+        let (id_1, _) = store.new_file("old", KnownExtension::Png).unwrap();
+        let (id_2, _) = store.new_file("old", KnownExtension::Png).unwrap();
+
+        store.set_title(id_1, "New");
+        assert_eq!(store.exact_title_lookup("old"), vec![id_2]);
+        assert_eq!(store.exact_title_lookup("new"), vec![id_1]);
+
+        store.remove(&id_2);
+        assert!(store.exact_title_lookup("old").is_empty());
+        assert!(!store.title_index.contains_key("old"));
+
+        store.clear();
+        assert!(store.exact_title_lookup("new").is_empty());
+    }
+
+    #[test]
+    fn retain_by_title() {
+        let mut store = FileStore::new();
+        let (sword, _) = store.new_file("sword", KnownExtension::Png).unwrap();
+        store.new_file("old shield", KnownExtension::Png).unwrap();
+        store.new_file("old sword", KnownExtension::Png).unwrap();
+
+        store.retain(|_, file| !file.title().starts_with("old"));
+
+        assert_eq!(store.count(), 1);
+        assert!(store.contains(sword));
+        assert!(store.exact_title_lookup("old shield").is_empty());
+    }
+
+    #[test]
+    fn clear_does_not_reuse_ids() {
+        let mut store = FileStore::new();
+        let (id_1, _) = store.new_file("first", KnownExtension::Png).unwrap();
+        let (id_2, _) = store.new_file("second", KnownExtension::Png).unwrap();
+
+        store.clear();
+        assert_eq!(store.count(), 0);
+
+        let (id_3, _) = store.new_file("third", KnownExtension::Png).unwrap();
+        assert_ne!(id_3, id_1);
+        assert_ne!(id_3, id_2);
+    }
+
+    #[test]
+    fn find_files_by_system_tag() {
+        let mut store = FileStore::new();
+        let (first, _) = store.new_file("first", KnownExtension::Png).unwrap();
+        let (opaque, _) = store.new_file("opaque", KnownExtension::Png).unwrap();
+        let (second, _) = store.new_file("second", KnownExtension::Png).unwrap();
+
+        store.add_system_tag(second, SystemTag::Transparent);
+        store.add_system_tag(first, SystemTag::Transparent);
+
+        let transparent = store.find_by_system_tag(SystemTag::Transparent);
+        assert_eq!(transparent, vec![first, second]);
+        assert!(!transparent.contains(&opaque));
+    }
+
+    #[test]
+    fn add_and_remove_system_tags() {
+        let mut store = FileStore::new();
+        let (id, _) = store.new_file("file", KnownExtension::Png).unwrap();
+
+        assert!(store.add_system_tag(id, SystemTag::Transparent));
+        let tagged_at = store.get(id).unwrap().modified_at();
+        assert!(!store.add_system_tag(id, SystemTag::Transparent));
+        assert_eq!(store.get(id).unwrap().modified_at(), tagged_at);
+        assert_eq!(store.get(id).unwrap().system_tags().len(), 1);
+
+        assert!(store.remove_system_tag(id, SystemTag::Transparent));
+        assert!(store.get(id).unwrap().system_tags().is_empty());
+        // Removing a tag that is not there is fine.
+        assert!(store.remove_system_tag(id, SystemTag::Transparent));
+    }
+
+    #[test]
+    fn system_tags_on_unknown_files() {
+        let mut store = FileStore::new();
+
+        assert!(!store.add_system_tag(FileId(3), SystemTag::Transparent));
+        assert!(!store.remove_system_tag(FileId(3), SystemTag::Transparent));
+    }
+
+    #[test]
+    fn set_title_trims_and_rejects_empty_titles() {
+        let mut store = FileStore::new();
+        let (id, _) = store.new_file("old", KnownExtension::Png).unwrap();
+
+        assert!(store.set_title(id, "  new  "));
+        assert_eq!(store.get(id).unwrap().title(), "new");
+        assert!(!store.set_title(id, "   "));
+        assert!(!store.set_title(id, "new\nline"));
+        assert_eq!(store.get(id).unwrap().title(), "new");
+        assert!(!store.set_title(FileId(99), "other"));
+    }
+
+    #[test]
+    fn titles_with_control_characters_are_rejected() {
+        let mut store = FileStore::new();
+
+        for title in &["", "  ", "two\nlines", "tab\tbed", "bell\u{7}", "null\0"] {
+            assert!(validate_title(title).is_err(), "{:?} was accepted", title);
+            assert!(store.new_file(title, KnownExtension::Png).is_err());
+            assert!(store
+                .insert_with_id(FileId(50), title, KnownExtension::Png)
+                .is_err());
+        }
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn unicode_titles_are_accepted() {
+        let mut store = FileStore::new();
+
+        for title in &["⚔️ Sword", "剣", "Épée", "مثال", "👨‍👩‍👧", "a\u{200b}b"]
+        {
+            assert_eq!(validate_title(title).unwrap(), *title);
+            let (id, _) = store.new_file(title, KnownExtension::Png).unwrap();
+            assert_eq!(store.get(id).unwrap().title(), *title);
+        }
+        assert_eq!(validate_title("  Sword ").unwrap(), "Sword");
+    }
+
+    #[test]
+    fn later_files_are_created_later() {
+        let mut store = FileStore::new();
+        let (id_1, _) = store.new_file("first", KnownExtension::Png).unwrap();
+        let (id_2, _) = store.new_file("second", KnownExtension::Png).unwrap();
+
+        assert!(store.get(id_2).unwrap().created_at() >= store.get(id_1).unwrap().created_at());
+    }
+
+    #[test]
+    fn ids_round_trip_through_strings() {
+        let id = FileId::from_u32(7);
+        assert_eq!(id.as_u32(), 7);
+        assert_eq!(id.to_string(), "7");
+        assert_eq!("7".parse::<FileId>().unwrap(), id);
+
+        assert!("seven".parse::<FileId>().is_err());
+        assert!("".parse::<FileId>().is_err());
+        assert!("7.png".parse::<FileId>().is_err());
+    }
+
+    /// File names are exactly `<id>.<ext>`, because they get joined onto the files directory.
+    #[test]
+    fn file_name_is_id_with_extension() {
+        let mut store = FileStore::new();
+
+        let (id, name) = store.new_file("first", KnownExtension::Png).unwrap();
+        assert_eq!(name, PathBuf::from("0.png"));
+
+        let stem = name.file_stem().unwrap().to_str().unwrap();
+        assert_eq!(stem, id.to_string());
+
+        // Parsing the stem back should give the original id.
+        assert_eq!(stem.parse::<FileId>().unwrap(), id);
+    }
+}
+
+#[cfg(test)]
+mod test_file_store_from_directory {
+    use super::*;
+
+    #[test]
+    fn picks_up_stored_files() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        std::fs::write(dir.join("0.png"), "zero").unwrap();
+        std::fs::write(dir.join("3.png"), "three").unwrap();
+        std::fs::write(dir.join("foo.txt"), "foo").unwrap();
+        std::fs::write(dir.join("bar.png"), "bar").unwrap();
+
+        let store = FileStore::from_directory(dir).unwrap();
+
+        assert_eq!(store.count(), 2);
+        assert_eq!(store.get(FileId(0)).unwrap().title(), "0");
+        assert_eq!(
+            store.get(FileId(3)).unwrap().file_name(),
+            Path::new("3.png")
+        );
+        assert_eq!(store.allocator.next_id(), 4);
+    }
+
+    #[test]
+    fn only_picks_up_exact_ids() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        std::fs::write(dir.join("3.png"), "three").unwrap();
+        std::fs::write(dir.join("03.png"), "zero three").unwrap();
+        std::fs::write(dir.join("+4.png"), "plus four").unwrap();
+
+        let store = FileStore::from_directory(dir).unwrap();
+
+        assert_eq!(store.count(), 1);
+        assert_eq!(
+            store.get(FileId(3)).unwrap().content_hash(),
+            Some(content_hash::hash_bytes(b"three").as_str())
+        );
+    }
+
+    #[test]
+    fn duplicate_ids_are_an_error() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let dir = tempdir.path();
+        std::fs::write(dir.join("3.png"), "png").unwrap();
+        std::fs::write(dir.join("3.jpg"), "jpg").unwrap();
+
+        assert!(FileStore::from_directory(dir).is_err());
+    }
+
+    #[test]
+    fn missing_directory_is_an_error() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        assert!(FileStore::from_directory(&tempdir.path().join("nope")).is_err());
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test_file_store_serde {
+    use super::*;
+
+    /// A store that is serialized and deserialized again should contain the exact same files.
+    #[test]
+    fn file_store_survives_json_round_trip() {
+        let mut store = FileStore::new();
+        let (id_1, _) = store.new_file("first", KnownExtension::Png).unwrap();
+        let (id_2, _) = store.new_file("second", KnownExtension::Png).unwrap();
+        store
+            .files
+            .get_mut(&id_2)
+            .unwrap()
+            .system_tags
+            .insert(SystemTag::Transparent);
+
+        let json = serde_json::to_string(&store).unwrap();
+        let loaded: FileStore = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded.count(), 2);
+        for (id, file) in store.iter() {
+            let loaded_file = loaded.get(*id).unwrap();
+            assert_eq!(loaded_file.id, file.id);
+            assert_eq!(loaded_file.title(), file.title());
+            assert_eq!(loaded_file.extension(), file.extension());
+            assert!(loaded_file.system_tags() == file.system_tags());
+            assert_eq!(loaded_file.created_at(), file.created_at());
+        }
+        assert!(loaded.get(id_1).unwrap().system_tags().is_empty());
+    }
+
+    /// After loading, new files should not reuse the ids of files that were already there.
+    #[test]
+    fn next_id_is_preserved() {
+        let mut store = FileStore::new();
+        let (id_1, _) = store.new_file("first", KnownExtension::Png).unwrap();
+        let (id_2, _) = store.new_file("second", KnownExtension::Png).unwrap();
+
+        let json = serde_json::to_string(&store).unwrap();
+        let mut loaded: FileStore = serde_json::from_str(&json).unwrap();
+
+        let (id_3, _) = loaded.new_file("third", KnownExtension::Png).unwrap();
+        assert_ne!(id_3, id_1);
+        assert_ne!(id_3, id_2);
+    }
+
+    /// Only where the allocator is at is saved, in the same place as before there were allocators.
+    #[test]
+    fn allocator_is_saved_as_next_id() {
+        let mut store = FileStore::with_next_id(7);
+        store.new_file("first", KnownExtension::Png).unwrap();
+
+        let json = serde_json::to_value(&store).unwrap();
+        assert_eq!(json["next_id"], 8);
+
+        let loaded: FileStore = serde_json::from_value(json).unwrap();
+        assert_eq!(loaded.allocator.next_id(), 8);
+    }
+
+    #[test]
+    fn metadata_survives_json_round_trip() {
+        let mut store = FileStore::new();
+        let (id, _) = store.new_file("Sword", KnownExtension::Png).unwrap();
+        store.set_metadata(id, "author", "x");
+        store.set_metadata(id, "license", "CC0");
+
+        let json = serde_json::to_string(&store).unwrap();
+        let loaded: FileStore = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            loaded.get(id).unwrap().metadata(),
+            store.get(id).unwrap().metadata()
+        );
+    }
+
+    #[test]
+    fn original_path_survives_json_round_trip() {
+        let mut store = FileStore::new();
+        let (id, _) = store.new_file("Sword", KnownExtension::Png).unwrap();
+        let (no_path, _) = store.new_file("Generated", KnownExtension::Png).unwrap();
+        assert!(store.set_original_path(id, Path::new("imports/sword.png")));
+
+        let json = serde_json::to_string(&store).unwrap();
+        let loaded: FileStore = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            loaded.get(id).unwrap().original_path(),
+            Some(Path::new("imports/sword.png"))
+        );
+        assert_eq!(loaded.get(no_path).unwrap().original_path(), None);
+    }
+
+    /// The title index is not serialized, so it has to be rebuilt on load.
+    #[test]
+    fn title_index_is_rebuilt() {
+        let mut store = FileStore::new();
+        let (id, _) = store.new_file("Title", KnownExtension::Png).unwrap();
+
+        let json = serde_json::to_string(&store).unwrap();
+        assert!(!json.contains("title_index"));
+        let loaded: FileStore = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded.exact_title_lookup("title"), vec![id]);
+    }
+}
+
+#[cfg(test)]
+mod test_file_extensions {
+    use super::*;
+    use naughty_strings::BLNS;
+
+    #[test]
+    fn unknown_file_extensions_should_return_none() {
+        assert!(KnownExtension::from_str("pdf").is_none());
+        assert!(KnownExtension::from_str("xcf").is_none());
+        assert!(KnownExtension::from_str("jpe").is_none());
+    }
+
+    #[test]
+    fn jpg_and_jpeg_are_the_same_extension() {
+        for string in &["jpg", "JPG", "jpeg", "JPEG", "Jpeg"] {
+            assert_eq!(
+                KnownExtension::from_str(string).unwrap(),
+                KnownExtension::Jpeg
+            );
+        }
+        assert_eq!(
+            KnownExtension::from_path(Path::new("photo.JPEG")).unwrap(),
+            KnownExtension::Jpeg
+        );
+    }
+
+    #[test]
+    fn gif_bmp_and_webp_round_trip() {
+        for (string, extension) in &[
+            ("gif", KnownExtension::Gif),
+            ("bmp", KnownExtension::Bmp),
+            ("webp", KnownExtension::WebP),
+            ("svg", KnownExtension::Svg),
+            ("tiff", KnownExtension::Tiff),
+            ("ico", KnownExtension::Ico),
+        ] {
+            assert_eq!(&KnownExtension::from_str(string).unwrap(), extension);
+            assert_eq!(
+                &KnownExtension::from_str(&string.to_uppercase()).unwrap(),
+                extension
+            );
+            assert_eq!(extension.to_str(), *string);
+        }
+    }
+
+    #[test]
+    fn known_image_extensions() {
+        assert!(KnownExtension::Png.is_image());
+        assert!(KnownExtension::Jpeg.is_image());
+        assert!(KnownExtension::Gif.is_image());
+        assert!(KnownExtension::Bmp.is_image());
+        assert!(KnownExtension::WebP.is_image());
+        assert!(!KnownExtension::Svg.is_image());
+        assert!(KnownExtension::Ico.is_image());
+        assert!(KnownExtension::Tiff.is_image());
+    }
+
+    #[test]
+    fn tif_and_tiff_are_both_tiff() {
+        assert_eq!(KnownExtension::from_str("tif"), Some(KnownExtension::Tiff));
+        assert_eq!(KnownExtension::from_str("TIFF"), Some(KnownExtension::Tiff));
+        assert_eq!(
+            KnownExtension::from_path(Path::new("scan.tif")),
+            Some(KnownExtension::Tiff)
+        );
+        assert_eq!(KnownExtension::Tiff.to_str(), "tiff");
+        assert_eq!(KnownExtension::Tiff.mime_type(), "image/tiff");
+        assert_eq!(KnownExtension::Ico.mime_type(), "image/x-icon");
+    }
+
+    #[test]
+    fn all_lists_every_extension() {
+        let all = KnownExtension::all();
+        for extension in all {
+            // Fails to compile when a variant is added, as a reminder to add it to `all`.
+            match extension {
+                KnownExtension::Png
+                | KnownExtension::Jpeg
+                | KnownExtension::Gif
+                | KnownExtension::Bmp
+                | KnownExtension::WebP
+                | KnownExtension::Svg
+                | KnownExtension::Tiff
+                | KnownExtension::Ico => {}
+            }
+        }
+        assert_eq!(all.len(), 8);
+
+        let strings = KnownExtension::all_strings();
+        assert_eq!(
+            strings,
+            vec!["png", "jpg", "gif", "bmp", "webp", "svg", "tiff", "ico"]
+        );
+        for (string, extension) in strings.iter().zip(all) {
+            assert_eq!(&KnownExtension::from_str(string).unwrap(), extension);
+        }
+    }
+
+    #[test]
+    fn only_svg_is_vector() {
+        assert!(KnownExtension::Svg.is_vector());
+        assert!(!KnownExtension::Png.is_vector());
+        assert!(!KnownExtension::Jpeg.is_vector());
+        assert_eq!(KnownExtension::Svg.mime_type(), "image/svg+xml");
+    }
+
+    #[test]
+    fn mime_types_and_display() {
+        assert_eq!(KnownExtension::Png.mime_type(), "image/png");
+        assert_eq!(KnownExtension::Jpeg.mime_type(), "image/jpeg");
+
+        assert_eq!(format!("{}", KnownExtension::Png), "png");
+        assert_eq!(format!("{}", KnownExtension::Jpeg), "jpg");
+    }
+
+    #[test]
+    fn jpeg_files_are_stored_as_jpg() {
+        let mut store = FileStore::new();
+
+        let (_, name) = store.new_file("photo", KnownExtension::Jpeg).unwrap();
+        assert_eq!(name.extension().unwrap(), "jpg");
+    }
+
+    #[test]
+    fn file_extensions_should_work_when_capitalized() {
+        assert_eq!(
+            KnownExtension::from_str("PNG").unwrap(),
+            KnownExtension::Png
+        );
+        assert_eq!(
+            KnownExtension::from_str("pnG").unwrap(),
+            KnownExtension::Png
+        );
+        assert_eq!(
+            KnownExtension::from_str("PnG").unwrap(),
+            KnownExtension::Png
+        );
+    }
+
+    #[test]
+    fn extensions_of_unusual_paths() {
+        let extension_of = |path: &str| KnownExtension::from_path(Path::new(path));
+
+        assert_eq!(extension_of("sprite.tar.png"), Some(KnownExtension::Png));
+        assert_eq!(extension_of("a.b.JPEG"), Some(KnownExtension::Jpeg));
+        assert_eq!(
+            extension_of("dir.gif/sprite.png"),
+            Some(KnownExtension::Png)
         );
+        assert_eq!(extension_of(".png"), None);
+        assert_eq!(extension_of("dir/.png"), None);
+        assert_eq!(extension_of("sprite.png "), None);
+        assert_eq!(extension_of("sprite. png"), None);
+        assert_eq!(extension_of("sprite.png."), None);
+        assert_eq!(extension_of("png"), None);
     }
 
     #[test]
@@ -0,0 +1,109 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Decides which ids a store hands out. Stores use a `SequentialAllocator` unless told otherwise,
+/// a different one can be used to give each shard of a distributed setup its own range of ids.
+pub trait IdAllocator {
+    /// The next id to hand out, or `None` when there are no more ids left.
+    /// Ids that the store already has are skipped by the store.
+    fn allocate(&mut self) -> Option<u32>;
+
+    /// Called when the store takes in an id that was not handed out by this allocator,
+    /// like when a store is rebuilt from disk, so that it is not handed out later.
+    fn mark_used(&mut self, id: u32);
+
+    /// Where the allocator is at, which is saved along with the store.
+    /// A loaded store continues sequentially from here, see `SequentialAllocator::starting_at`.
+    fn next_id(&self) -> u32;
+}
+
+/// Hands out ids counting up from where it starts. The last possible id is never handed out.
+pub struct SequentialAllocator {
+    next_id: u32,
+}
+
+impl SequentialAllocator {
+    pub fn new() -> SequentialAllocator {
+        SequentialAllocator::starting_at(0)
+    }
+
+    pub fn starting_at(next_id: u32) -> SequentialAllocator {
+        SequentialAllocator { next_id }
+    }
+}
+
+impl Default for SequentialAllocator {
+    fn default() -> SequentialAllocator {
+        SequentialAllocator::new()
+    }
+}
+
+impl IdAllocator for SequentialAllocator {
+    fn allocate(&mut self) -> Option<u32> {
+        let id = self.next_id;
+        self.next_id = id.checked_add(1)?;
+        Some(id)
+    }
+
+    fn mark_used(&mut self, id: u32) {
+        // When the last possible id is used, there is simply nothing left to hand out.
+        if id >= self.next_id {
+            self.next_id = id.saturating_add(1);
+        }
+    }
+
+    fn next_id(&self) -> u32 {
+        self.next_id
+    }
+}
+
+/// Only where the allocator is at is saved, as a plain number.
+#[cfg(feature = "serde")]
+impl Serialize for dyn IdAllocator {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.next_id().serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Box<dyn IdAllocator> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let next_id = u32::deserialize(deserializer)?;
+        Ok(Box::new(SequentialAllocator::starting_at(next_id)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sequential_counts_up() {
+        let mut allocator = SequentialAllocator::new();
+
+        assert_eq!(allocator.allocate(), Some(0));
+        assert_eq!(allocator.allocate(), Some(1));
+        assert_eq!(allocator.next_id(), 2);
+    }
+
+    #[test]
+    fn sequential_skips_past_used_ids() {
+        let mut allocator = SequentialAllocator::new();
+
+        allocator.mark_used(5);
+        allocator.mark_used(3);
+        assert_eq!(allocator.allocate(), Some(6));
+    }
+
+    #[test]
+    fn sequential_runs_out() {
+        let mut allocator = SequentialAllocator::starting_at(u32::MAX - 1);
+
+        assert_eq!(allocator.allocate(), Some(u32::MAX - 1));
+        assert_eq!(allocator.allocate(), None);
+
+        let mut allocator = SequentialAllocator::new();
+        allocator.mark_used(u32::MAX);
+        assert_eq!(allocator.allocate(), None);
+    }
+}
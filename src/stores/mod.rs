@@ -1,2 +1,5 @@
+pub mod asset_store;
+pub mod collection_store;
 pub mod file_store;
+pub mod id_allocator;
 pub mod traits;
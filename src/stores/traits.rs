@@ -1,5 +1,11 @@
-use std::collections::hash_map::Iter;
+use anyhow::Result;
+use std::fmt::Display;
 use std::hash::Hash;
+use std::str::FromStr;
+
+/// Iterator over the ids and items in a store.
+/// Boxed, so that stores are free to choose how they keep their items.
+pub type StoreIter<'a, Id, Item> = Box<dyn Iterator<Item = (&'a Id, &'a Item)> + 'a>;
 
 pub trait IndexedStore {
     type Id: StoreId;
@@ -7,11 +13,72 @@ pub trait IndexedStore {
 
     fn get(&self, id: Self::Id) -> Option<&Self::Item>;
 
+    fn get_mut(&mut self, id: Self::Id) -> Option<&mut Self::Item>;
+
     fn count(&self) -> usize;
 
+    /// What a single item in the store is called, like "file", for messages and logging.
+    fn item_kind(&self) -> &'static str;
+
+    /// Looks up multiple items at once. The results are in the same order as the ids,
+    /// with `None` for ids that are not in the store.
+    fn get_many(&self, ids: &[Self::Id]) -> Vec<Option<&Self::Item>> {
+        ids.iter().map(|&id| self.get(id)).collect()
+    }
+
+    fn contains(&self, id: Self::Id) -> bool {
+        self.get(id).is_some()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.count() == 0
+    }
+
     fn remove(&mut self, id: &Self::Id) -> Option<Self::Item>;
 
-    fn iter(&self) -> Iter<Self::Id, Self::Item>;
+    /// Removes all the items. Ids that were handed out before will not be handed out again.
+    fn clear(&mut self);
+
+    /// Removes all the items for which `keep` returns false.
+    fn retain<F>(&mut self, keep: F)
+    where
+        F: FnMut(&Self::Id, &Self::Item) -> bool,
+        Self: Sized;
+
+    fn iter(&self) -> StoreIter<'_, Self::Id, Self::Item>;
+
+    // Not available on trait objects, so that `dyn IndexedStore` can still be used.
+    fn ids(&self) -> impl Iterator<Item = &Self::Id>
+    where
+        Self: Sized,
+    {
+        self.iter().map(|(id, _)| id)
+    }
+
+    fn values(&self) -> impl Iterator<Item = &Self::Item>
+    where
+        Self: Sized,
+    {
+        self.iter().map(|(_, item)| item)
+    }
+}
+
+/// How many items the store has, like "3 file(s)".
+pub fn describe_count<S: IndexedStore + ?Sized>(store: &S) -> String {
+    format!("{} {}(s)", store.count(), store.item_kind())
 }
 
-pub trait StoreId: Eq + PartialEq + Hash + Copy + Clone {}
+/// Stores that can take in items that were made elsewhere, like when migrating between stores.
+pub trait MutableStore: IndexedStore {
+    /// Adds the item under a newly handed out id, which replaces the id the item had.
+    /// Will return an error when the item is not valid for the store,
+    /// or when there are no more ids left to hand out.
+    fn insert(&mut self, item: Self::Item) -> Result<Self::Id>;
+}
+
+/// Ids are numbers on the inside, which is also how they are shown and parsed as strings.
+pub trait StoreId: Eq + PartialEq + Hash + Copy + Clone + Display + FromStr {
+    fn from_u32(id: u32) -> Self;
+
+    fn as_u32(&self) -> u32;
+}